@@ -32,9 +32,23 @@ use std::env;
 use std::path::PathBuf;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // build tonic protocol
-    tonic_build::compile_protos("third-party/common/protobuf/grpc_service.proto")?;
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    // build tonic protocol, keeping the descriptors for the model configuration text format
+    tonic_build::configure()
+        .file_descriptor_set_path(out_path.join("inference_descriptor.bin"))
+        .compile(&["third-party/common/protobuf/grpc_service.proto"], &["third-party/common/protobuf"])?;
+
+    #[cfg(feature = "cuda")]
+    build_cuda_shared_memory(&out_path);
+
+    Ok(())
+}
 
+// CUDA shared memory is provided by a C++ library built with `make lib`, it is only
+// linked with the `cuda` feature so that the crate builds without the CUDA toolkit.
+#[cfg(feature = "cuda")]
+fn build_cuda_shared_memory(out_path: &std::path::Path) {
     // link with shared library
     let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
     println!("cargo:rustc-link-search={}/src/shared_memory/", manifest_dir);
@@ -60,9 +74,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .expect("Unable to generate bindings");
 
     // Write the bindings to the $OUT_DIR/bindings.rs file.
-    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
     bindings
         .write_to_file(out_path.join("shared_memory_binding.rs"))
         .expect("Couldn't write bindings!");
-    Ok(())
 }
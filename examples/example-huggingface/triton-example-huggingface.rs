@@ -30,13 +30,10 @@ knowledge of the CeCILL-B license and that you accept its terms.*/
 
 use std::process;
 use ndarray::{Array};
-use std::vec::Vec;
-use std::collections::HashMap;
 
 use tokenizers::tokenizer::{Result, Tokenizer};
 
 use triton_rust::TritonInference;
-use triton_rust::inference::model_infer_request::{InferInputTensor, InferRequestedOutputTensor};
 
 fn main() -> Result<()> {
     let mut triton_inferer = TritonInference::connect("http://127.0.0.1:71").unwrap();
@@ -48,39 +45,32 @@ fn main() -> Result<()> {
         process::exit(1);
     }
 
-    let model_metadata = triton_inferer.get_model_metadata("distilbert-base-uncased", "1").unwrap();
+    /* Fetch the model metadata used to check the requests */
+    let model = triton_inferer.get_model("distilbert-base-uncased", "1").unwrap();
 
     /* Initialize the tokenizer with the proper model */
     let tokenizer = Tokenizer::from_pretrained("bert-base-cased", None).unwrap();
 
     /* Encode the sentence */
     let encoding = tokenizer.encode("Hello world!", false)?;
-    let tokens = encoding.get_ids().iter().map(|x| *x as i64).collect();
-    let mask = encoding.get_attention_mask().iter().map(|x| *x as i64).collect();
+    let tokens: Vec<i64> = encoding.get_ids().iter().map(|x| *x as i64).collect();
+    let mask: Vec<i64> = encoding.get_attention_mask().iter().map(|x| *x as i64).collect();
 
-    let tokens_array = Array::from_vec(tokens);
-    let mask_array = Array::from_vec(mask);
+    let tokens_array = Array::from_shape_vec((1, encoding.len()), tokens)?;
+    let mask_array = Array::from_shape_vec((1, encoding.len()), mask)?;
 
-    /* Prepare the data to be sent to Triton */
-    let tokens_content = triton_inferer.get_input_content_from_ndarray(&tokens_array);
-    let mask_content = triton_inferer.get_input_content_from_ndarray(&mask_array);
+    /* Prepare the request to be sent to Triton */
+    let request = model.request()
+        .id("25")
+        .input("input_ids", &tokens_array)
+        .input("attention_mask", &mask_array)
+        .output("logits");
 
-    let mut infer_inputs = Vec::<InferInputTensor>::with_capacity(2);
-    let mut inputs_content = Vec::<Vec<u8>>::with_capacity(2);
+    let response  = triton_inferer.infer_model(&model, request).unwrap();
 
-    infer_inputs.push(triton_inferer.get_infer_input("input_ids", "INT64", &[1, 3], HashMap::new()));
-    inputs_content.push(tokens_content);
-
-    infer_inputs.push(triton_inferer.get_infer_input("attention_mask", "INT64", &[1, 3], HashMap::new()));
-    inputs_content.push(mask_content);
-
-
-    let response  = triton_inferer.infer("distilbert-base-uncased", "1", "25", infer_inputs, Vec::<InferRequestedOutputTensor>::new(), inputs_content).unwrap();
-
-    /* Get the logits raw data */
-    let output_logits = unsafe { response.raw_output_contents[0].align_to::<f32>().1 };
-    let array_nd = Array::from_iter(output_logits.iter());
-    println!("{:?}", array_nd.into_shape((1, 3, model_metadata.outputs[0].shape[2] as usize)));
+    /* Get the logits */
+    let output_logits = response.output::<f32>("logits")?;
+    println!("{:?}", output_logits);
 
     Ok(())
 }
@@ -30,12 +30,10 @@ knowledge of the CeCILL-B license and that you accept its terms.*/
 
 use std::process;
 use std::error::Error;
-use std::vec::Vec;
-use ndarray::{array};
+use ndarray::Axis;
 use nshare::ToNdarray3;
 
 use triton_rust::TritonInference;
-use triton_rust::inference::model_infer_request::{InferInputTensor, InferRequestedOutputTensor};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let mut triton_inferer = TritonInference::connect("http://127.0.0.1:71").unwrap();
@@ -47,53 +45,37 @@ fn main() -> Result<(), Box<dyn Error>> {
         process::exit(1);
     }
 
-    let _model_metadata = triton_inferer.get_model_metadata("resnet18-imagenet", "1").unwrap();
+    /* Fetch the model metadata used to check the requests */
+    let model = triton_inferer.get_model("resnet18-imagenet", "1")?;
 
     let img = image::open("examples/example-imagenet/dog.jpeg").unwrap().into_rgb8();
     let img_ndarray = img.into_ndarray3();
-    let mut img_ndarray_f32 = img_ndarray.mapv(|elem| (elem as f32) / 256.0);
 
-    /* Create the array with mean and std for ImageNet */
-    let imagenet_mean = array![0.485, 0.456, 0.406].mapv(|elem: f64| (elem as f32));
-    let imagenet_std =  array![0.229, 0.224, 0.225].mapv(|elem: f64| (elem as f32));
-
-    let mut imagenet_mean_broadcasted = imagenet_mean.broadcast((256, 256, 3)).unwrap();
-    let mut imagenet_std_broadcasted = imagenet_std.broadcast((256, 256, 3)).unwrap();
-    imagenet_mean_broadcasted.swap_axes(0,2);
-    imagenet_std_broadcasted.swap_axes(0,2);
-
-    /* Normalize the array */
-    img_ndarray_f32 = (img_ndarray_f32 - imagenet_mean_broadcasted) / imagenet_std_broadcasted;
-
-    /* Make the memory zone contiguous in standard layout */
-    let img_ndarray_f32_continuous = img_ndarray_f32.as_standard_layout();
-
-    let size_of_image: u64 = 3 * 256 * 256 * 4;
-    let output_size: u64 = 1000 * 4;
-
-    /* Create shared memory zones */
-    let mut system_mem_zone_input = triton_inferer.create_system_shared_memory("input_data", "/input_data", size_of_image).unwrap();
-    let mut system_mem_zone_output = triton_inferer.create_system_shared_memory("output_data", "/output_data", output_size).unwrap();
-
-    /* Create input parameters */
-    let mut infer_inputs = Vec::<InferInputTensor>::with_capacity(1);
-
-    let input_params = triton_inferer.get_system_shared_memory_params("input_data", size_of_image, 0);
-    infer_inputs.push(triton_inferer.get_infer_input("input_data", "FP32", &[3, 256, 256], input_params));
-
-    system_mem_zone_input.copy_array(&img_ndarray_f32_continuous, 0);
-
-    /* Create output parameters */
-    let mut infer_outputs = Vec::<InferRequestedOutputTensor>::with_capacity(1);
-
-    let outputs_params = triton_inferer.get_system_shared_memory_params("output_data", output_size, 0);
-    infer_outputs.push(triton_inferer.get_infer_output("output_data", outputs_params));
-
-    /* Inference */
-    let _response  = triton_inferer.infer("resnet18-imagenet", "1", "25", infer_inputs, infer_outputs, Vec::<Vec<u8>>::new()).unwrap();
-
-    /* Get the output */
-    let outputs: Vec<f32> = system_mem_zone_output.get_data(output_size, 0);
+    /* Mean and std for ImageNet */
+    let imagenet_mean = [0.485f32, 0.456, 0.406];
+    let imagenet_std = [0.229f32, 0.224, 0.225];
+
+    /* Create a shared memory pool holding the input and the output */
+    let pool = triton_inferer.create_system_shared_memory_pool("imagenet", 1, 1024 * 1024)?;
+    let mut input = pool.allocate(3 * 256 * 256 * 4)?;
+    let output = pool.allocate(1000 * 4)?;
+
+    /* Normalize the image directly into shared memory, input and output are read from and written to shared memory */
+    let request = model.request()
+        .id("25")
+        .shm_input_with::<f32, _>("input", &[1, 3, 256, 256], &mut input, |mut tensor| {
+            for (channel, mut plane) in tensor.index_axis_mut(Axis(0), 0).outer_iter_mut().enumerate() {
+                plane.zip_mut_with(&img_ndarray.index_axis(Axis(0), channel), |value, pixel| {
+                    *value = ((*pixel as f32) / 256.0 - imagenet_mean[channel]) / imagenet_std[channel];
+                });
+            }
+        })?
+        .shm_output_slice("output", &output);
+
+    let _response  = triton_inferer.infer_model(&model, request)?;
+
+    /* Read the output in place */
+    let outputs = output.view::<f32>(0, &[1000])?;
     println!("{:?}", outputs);
 
     Ok(())
@@ -0,0 +1,512 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use ndarray::{ArrayBase, Data, Dimension};
+use tonic::metadata::{AsciiMetadataKey, AsciiMetadataValue, KeyAndValueRef, MetadataMap};
+use tonic::service::Interceptor;
+
+use crate::inference::grpc_inference_service_client::GrpcInferenceServiceClient;
+use crate::inference::{ServerLiveRequest, ServerReadyRequest, ModelReadyRequest};
+use crate::inference::{InferParameter, ModelInferRequest};
+use crate::inference::{ModelMetadataRequest, ModelMetadataResponse};
+use crate::inference::{ModelConfig, ModelConfigRequest};
+use crate::inference::{ServerMetadataRequest, ServerMetadataResponse, ModelStatisticsRequest, ModelStatisticsResponse};
+use crate::inference::{RepositoryIndexRequest, RepositoryIndexResponse, RepositoryModelLoadRequest, RepositoryModelUnloadRequest};
+use crate::inference::{ModelRepositoryParameter, model_repository_parameter};
+use crate::inference::{TraceSettingRequest, TraceSettingResponse, trace_setting_request};
+use crate::inference::{LogSettingsRequest, LogSettingsResponse, log_settings_request};
+use crate::inference::model_infer_request::{InferInputTensor, InferRequestedOutputTensor};
+#[cfg(feature = "cuda")]
+use crate::inference::{CudaSharedMemoryRegisterRequest, CudaSharedMemoryStatusRequest, CudaSharedMemoryStatusResponse};
+#[cfg(feature = "cuda")]
+use crate::inference::{CudaSharedMemoryUnregisterRequest, CudaSharedMemoryUnregisterResponse};
+use crate::inference::{SystemSharedMemoryRegisterRequest, SystemSharedMemoryStatusRequest, SystemSharedMemoryStatusResponse};
+use crate::inference::{SystemSharedMemoryUnregisterRequest, SystemSharedMemoryUnregisterResponse};
+use crate::builder::ClientBuilder;
+use crate::error::TritonError;
+use crate::model::Model;
+use crate::repository::ModelLoadOptions;
+use crate::registration::{Registration, SharedMemoryKind};
+use crate::request::{self, InferRequest};
+use crate::response::InferResponse;
+use crate::retry::{RetryClass, RetryPolicy, retry_class};
+use crate::sequence::Sequence;
+use crate::shared_memory_pool::SystemSharedMemoryPool;
+use crate::tensor::{DataType, Tensor, TensorElement};
+use crate::stream::{self, InferStreamSender, InferStream};
+#[cfg(feature = "cuda")]
+use crate::cuda_shared_memory;
+use crate::system_shared_memory;
+
+/// Asynchronous client for Triton Inference Server.
+///
+/// Cloning the client is cheap: all clones share the same underlying gRPC channel,
+/// so it can be stored in an application state and used from concurrent tasks.
+#[derive(Clone)]
+pub struct AsyncTritonClient {
+    client: GrpcInferenceServiceClient<tonic::transport::Channel>,
+    metadata: MetadataMap,
+    interceptor: Option<Arc<Mutex<dyn Interceptor + Send>>>,
+    retry_policy: Option<RetryPolicy>
+}
+
+impl AsyncTritonClient {
+    pub async fn connect<A: Into<String>>(address: A) -> Result<Self, TritonError> {
+        ClientBuilder::new(address).connect().await
+    }
+
+    pub fn from_client(client: GrpcInferenceServiceClient<tonic::transport::Channel>) -> Self {
+        AsyncTritonClient { client, metadata: MetadataMap::new(), interceptor: None, retry_policy: None }
+    }
+
+    /// Returns a client sending `metadata` with every request, in addition to the
+    /// metadata already configured. Values of existing keys are replaced.
+    ///
+    /// The returned client shares the channel of `self`, so this can be used to
+    /// override the metadata of a single call.
+    pub fn with_metadata(&self, metadata: &MetadataMap) -> Self {
+        let mut client = self.clone();
+
+        for entry in metadata.iter() {
+            match entry {
+                KeyAndValueRef::Ascii(key, _) => { client.metadata.remove(key); }
+                KeyAndValueRef::Binary(key, _) => { client.metadata.remove_bin(key); }
+            }
+        }
+        for entry in metadata.iter() {
+            match entry {
+                KeyAndValueRef::Ascii(key, value) => { client.metadata.append(key.clone(), value.clone()); }
+                KeyAndValueRef::Binary(key, value) => { client.metadata.append_bin(key.clone(), value.clone()); }
+            }
+        }
+
+        client
+    }
+
+    /// Returns a client sending the header `key: value` with every request.
+    pub fn with_header(&self, key: &str, value: &str) -> Result<Self, TritonError> {
+        let mut metadata = MetadataMap::new();
+        metadata.insert(parse_metadata_key(key)?, parse_metadata_value(value)?);
+
+        Ok(self.with_metadata(&metadata))
+    }
+
+    /// Returns a client calling `interceptor` on every request, after the static metadata
+    /// is set. This can refresh authentication tokens or inject trace context.
+    pub fn with_interceptor<I: Interceptor + Send + 'static>(&self, interceptor: I) -> Self {
+        self.with_shared_interceptor(Arc::new(Mutex::new(interceptor)))
+    }
+
+    pub(crate) fn with_shared_interceptor(&self, interceptor: Arc<Mutex<dyn Interceptor + Send>>) -> Self {
+        let mut client = self.clone();
+        client.interceptor = Some(interceptor);
+        client
+    }
+
+    /// Returns a client retrying failed calls according to `policy`.
+    pub fn with_retry_policy(&self, policy: RetryPolicy) -> Self {
+        let mut client = self.clone();
+        client.retry_policy = Some(policy);
+        client
+    }
+
+    pub fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry_policy.as_ref()
+    }
+
+    // Sends a unary call, retried according to the class of `method` (the gRPC method name)
+    async fn unary<M, R, F, Fut>(&self, message: M, method: &str, rpc: F) -> Result<tonic::Response<R>, TritonError>
+    where
+        M: Clone,
+        F: Fn(GrpcInferenceServiceClient<tonic::transport::Channel>, tonic::Request<M>) -> Fut,
+        Fut: Future<Output = Result<tonic::Response<R>, tonic::Status>>,
+    {
+        self.call(message, retry_class(method), rpc).await
+    }
+
+    // Sends a unary call, building a new request with the client metadata at each attempt
+    async fn call<M, R, F, Fut>(&self, message: M, retry: RetryClass, rpc: F) -> Result<tonic::Response<R>, TritonError>
+    where
+        M: Clone,
+        F: Fn(GrpcInferenceServiceClient<tonic::transport::Channel>, tonic::Request<M>) -> Fut,
+        Fut: Future<Output = Result<tonic::Response<R>, tonic::Status>>,
+    {
+        let policy = match &self.retry_policy {
+            Some(policy) if policy.allows(retry) => policy,
+            _ => return Ok(rpc(self.client.clone(), self.request(message)?).await?),
+        };
+
+        // The message is only cloned while another attempt is possible
+        let mut message = Some(message);
+        let mut attempt = 1;
+        loop {
+            let last_attempt = attempt >= policy.get_max_attempts();
+            let payload = if last_attempt { message.take().unwrap() } else { message.clone().unwrap() };
+
+            let err = match rpc(self.client.clone(), self.request(payload)?).await {
+                Ok(response) => return Ok(response),
+                Err(status) => TritonError::from(status),
+            };
+
+            if last_attempt || !policy.is_retryable(&err) {
+                return Err(match attempt {
+                    1 => err,
+                    attempts => TritonError::Retried { attempts, error: Box::new(err) },
+                });
+            }
+
+            tokio::time::sleep(policy.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    // Same error type as `Interceptor::call`
+    #[allow(clippy::result_large_err)]
+    fn request<T>(&self, message: T) -> Result<tonic::Request<T>, tonic::Status> {
+        let mut request = tonic::Request::new(());
+        *request.metadata_mut() = self.metadata.clone();
+
+        if let Some(interceptor) = &self.interceptor {
+            request = interceptor.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).call(request)?;
+        }
+
+        let (metadata, extensions, ()) = request.into_parts();
+        Ok(tonic::Request::from_parts(metadata, extensions, message))
+    }
+
+    pub fn inner(&self) -> GrpcInferenceServiceClient<tonic::transport::Channel> {
+        self.client.clone()
+    }
+
+    pub async fn is_server_live(&self) -> Result<bool,  TritonError> {
+        let message = ServerLiveRequest {};
+
+        let response = self.unary(message, "ServerLive", |mut client, request| async move { client.server_live(request).await }).await?;
+
+        Ok(response.get_ref().live)
+    }
+
+    pub async fn is_server_ready(&self) -> Result<bool,  TritonError> {
+        let message = ServerReadyRequest {};
+
+        let response = self.unary(message, "ServerReady", |mut client, request| async move { client.server_ready(request).await }).await?;
+
+        Ok(response.get_ref().ready)
+    }
+
+    pub async fn is_model_ready(&self, model_name: &str, version_number: &str) -> Result<bool,  TritonError> {
+        let message = ModelReadyRequest {name: model_name.to_string(), version: version_number.to_string()};
+
+        let response = self.unary(message, "ModelReady", |mut client, request| async move { client.model_ready(request).await }).await?;
+
+        Ok(response.get_ref().ready)
+    }
+
+    pub async fn get_server_metadata(&self) -> Result<ServerMetadataResponse,  TritonError> {
+        let message = ServerMetadataRequest {};
+
+        let response = self.unary(message, "ServerMetadata", |mut client, request| async move { client.server_metadata(request).await }).await?;
+
+        Ok(response.into_inner())
+    }
+
+    pub fn get_infer_input(&self, input_name: &str, input_datatype: DataType, tensor_shape: &[i64], parameters_map: HashMap<String, InferParameter>) -> InferInputTensor {
+
+        InferInputTensor {
+            name: input_name.to_string(),
+            datatype: input_datatype.as_str().to_string(),
+            shape: tensor_shape.to_vec(),
+            parameters: parameters_map,
+            contents: None
+        }
+    }
+
+    pub fn get_infer_output(&self, input_name: &str, parameters_map: HashMap<String, InferParameter>) -> InferRequestedOutputTensor {
+
+        InferRequestedOutputTensor {
+            name: input_name.to_string(),
+            parameters: parameters_map
+        }
+    }
+
+    pub async fn get_model_metadata(&self, model_name: &str, model_version: &str) -> Result<ModelMetadataResponse,  TritonError> {
+        let message = ModelMetadataRequest {name: model_name.to_string(), version: model_version.to_string()};
+
+        let response = self.unary(message, "ModelMetadata", |mut client, request| async move { client.model_metadata(request).await }).await?;
+
+        Ok(response.into_inner())
+    }
+
+    pub async fn get_model_config(&self, model_name: &str, model_version: &str) -> Result<ModelConfig,  TritonError> {
+        let message = ModelConfigRequest {name: model_name.to_string(), version: model_version.to_string()};
+
+        let response = self.unary(message, "ModelConfig", |mut client, request| async move { client.model_config(request).await }).await?;
+
+        response.into_inner().config.ok_or_else(|| TritonError::InvalidArgument(format!("server returned no configuration for model '{}'", model_name)))
+    }
+
+    /// Returns the statistics of a model version, or of all models if the name is empty.
+    pub async fn get_model_statistics(&self, model_name: &str, model_version: &str) -> Result<ModelStatisticsResponse,  TritonError> {
+        let message = ModelStatisticsRequest {name: model_name.to_string(), version: model_version.to_string()};
+
+        let response = self.unary(message, "ModelStatistics", |mut client, request| async move { client.model_statistics(request).await }).await?;
+
+        Ok(response.into_inner())
+    }
+
+    /// Lists the models of a repository (all repositories if the name is empty),
+    /// `ready` restricts the index to the models ready for inference.
+    pub async fn get_repository_index(&self, repository_name: &str, ready: bool) -> Result<RepositoryIndexResponse,  TritonError> {
+        let message = RepositoryIndexRequest {repository_name: repository_name.to_string(), ready};
+
+        let response = self.unary(message, "RepositoryIndex", |mut client, request| async move { client.repository_index(request).await }).await?;
+
+        Ok(response.into_inner())
+    }
+
+    /// Loads or reloads a model, `options` can override its configuration and files.
+    pub async fn load_model(&self, model_name: &str, options: ModelLoadOptions) -> Result<(),  TritonError> {
+        let message = RepositoryModelLoadRequest {
+            repository_name: String::new(),
+            model_name: model_name.to_string(),
+            parameters: options.into_parameters()?
+        };
+
+        self.unary(message, "RepositoryModelLoad", |mut client, request| async move { client.repository_model_load(request).await }).await?;
+
+        Ok(())
+    }
+
+    /// Unloads a model, `unload_dependents` also unloads the models it depends on (ensemble steps).
+    pub async fn unload_model(&self, model_name: &str, unload_dependents: bool) -> Result<(),  TritonError> {
+        let message = RepositoryModelUnloadRequest {
+            repository_name: String::new(),
+            model_name: model_name.to_string(),
+            parameters: HashMap::from([
+                ("unload_dependents".to_string(), ModelRepositoryParameter { parameter_choice: Some(model_repository_parameter::ParameterChoice::BoolParam(unload_dependents)) })
+            ])
+        };
+
+        self.unary(message, "RepositoryModelUnload", |mut client, request| async move { client.repository_model_unload(request).await }).await?;
+
+        Ok(())
+    }
+
+    /// Returns the trace settings of a model, or the global ones if the name is empty.
+    pub async fn get_trace_settings(&self, model_name: &str) -> Result<TraceSettingResponse,  TritonError> {
+        self.update_trace_settings(model_name, HashMap::new()).await
+    }
+
+    /// Updates trace settings (e.g. `trace_level`, `trace_rate`), an empty value list
+    /// resets a setting to its global value. Returns the resulting settings.
+    pub async fn update_trace_settings(&self, model_name: &str, settings: HashMap<String, Vec<String>>) -> Result<TraceSettingResponse,  TritonError> {
+        let message = TraceSettingRequest {
+            settings: settings.into_iter().map(|(key, value)| (key, trace_setting_request::SettingValue { value })).collect(),
+            model_name: model_name.to_string()
+        };
+
+        let response = self.unary(message, "TraceSetting", |mut client, request| async move { client.trace_setting(request).await }).await?;
+
+        Ok(response.into_inner())
+    }
+
+    pub async fn get_log_settings(&self) -> Result<LogSettingsResponse,  TritonError> {
+        self.update_log_settings(HashMap::new()).await
+    }
+
+    /// Updates log settings (e.g. `log_verbose_level`, `log_info`). Returns the resulting settings.
+    pub async fn update_log_settings(&self, settings: HashMap<String, log_settings_request::SettingValue>) -> Result<LogSettingsResponse,  TritonError> {
+        let message = LogSettingsRequest { settings };
+
+        let response = self.unary(message, "LogSettings", |mut client, request| async move { client.log_settings(request).await }).await?;
+
+        Ok(response.into_inner())
+    }
+
+    /// Returns a handle on a model version, fetching its metadata and configuration.
+    pub async fn get_model(&self, model_name: &str, model_version: &str) -> Result<Model,  TritonError> {
+        Model::fetch(self.clone(), model_name, model_version).await
+    }
+
+    pub async fn infer(&self, request: InferRequest) -> Result<InferResponse,  TritonError> {
+        self.model_infer(request.build()?).await
+    }
+
+    pub async fn model_infer(&self, request: ModelInferRequest) -> Result<InferResponse,  TritonError> {
+        self.send_infer(request, retry_class("ModelInfer")).await
+    }
+
+    async fn send_infer(&self, request: ModelInferRequest, retry: RetryClass) -> Result<InferResponse,  TritonError> {
+
+        let response = self.call(request, retry, |mut client, request| async move { client.model_infer(request).await }).await?;
+
+        Ok(InferResponse::new(response.into_inner()))
+    }
+
+    /// Sends a request as part of a sequence, `last` marks the end of the sequence.
+    pub async fn infer_sequence(&self, sequence: &mut Sequence, request: InferRequest, last: bool) -> Result<InferResponse,  TritonError> {
+
+        let mut request = request.build()?;
+        let (started, ended) = (sequence.is_started(), sequence.is_ended());
+        sequence.tag(&mut request, last)?;
+
+        let response = self.send_infer(request, RetryClass::Never).await;
+        if response.is_err() {
+            sequence.restore(started, ended);
+        }
+
+        response
+    }
+
+    /// Opens a bidirectional streaming session (`ModelStreamInfer`), required for decoupled
+    /// models which send zero or many responses per request.
+    pub fn stream_infer(&self) -> (InferStreamSender, InferStream) {
+        stream::open(self.client.clone(), self.request(()))
+    }
+
+    pub fn get_input_content_from_ndarray<T: TensorElement, S: Data<Elem = T>, D: Dimension>(&self, input_array: &ArrayBase<S, D>) -> Result<Vec<u8>, TritonError> {
+
+        Ok(Tensor::from_array("", input_array)?.into_data())
+    }
+
+    #[cfg(feature = "cuda")]
+    pub async fn create_cuda_shared_memory(&self, name: &str, size: usize, device_id: i64) -> Result<cuda_shared_memory::CudaSharedMemoryRegionHandle,  TritonError> {
+
+        let mut cuda_handle = cuda_shared_memory::CudaSharedMemoryRegionHandle::create(name, size, device_id)?;
+        // The region is freed if the registration fails
+        let cuda_raw_handle = cuda_handle.get_raw_handle()?;
+
+        let message = CudaSharedMemoryRegisterRequest {
+            name: name.to_string(),
+            raw_handle: cuda_raw_handle,
+            device_id,
+            byte_size: (size as u64)
+        };
+
+        let _response = self.unary(message, "CudaSharedMemoryRegister", |mut client, request| async move { client.cuda_shared_memory_register(request).await }).await?;
+        cuda_handle.set_registration(Registration::new(self.clone(), SharedMemoryKind::Cuda));
+
+        Ok(cuda_handle)
+    }
+
+    #[cfg(feature = "cuda")]
+    pub async fn cuda_shared_memory_status(&self, name: &str) -> Result<CudaSharedMemoryStatusResponse,  TritonError> {
+
+        let message = CudaSharedMemoryStatusRequest {
+            name: name.to_string()
+        };
+
+        let response = self.unary(message, "CudaSharedMemoryStatus", |mut client, request| async move { client.cuda_shared_memory_status(request).await }).await?;
+
+        Ok(response.into_inner())
+    }
+
+    #[cfg(feature = "cuda")]
+    pub async fn unregister_cuda_shared_memory(&self, name: &str) -> Result<CudaSharedMemoryUnregisterResponse,  TritonError> {
+
+        let message = CudaSharedMemoryUnregisterRequest {
+            name: name.to_string()
+        };
+
+        let response = self.unary(message, "CudaSharedMemoryUnregister", |mut client, request| async move { client.cuda_shared_memory_unregister(request).await }).await?;
+
+        Ok(response.into_inner())
+    }
+
+    pub async fn create_system_shared_memory(&self, name: &str, key: &str, size: usize) -> Result<system_shared_memory::SystemSharedMemoryRegionHandle,  TritonError> {
+
+        // The region is unlinked if the registration fails
+        let mut shm_handle = system_shared_memory::SystemSharedMemoryRegionHandle::create(name, key, size)?;
+
+        let message = SystemSharedMemoryRegisterRequest {
+            name: name.to_string(),
+            key: key.to_string(),
+            offset: 0,
+            byte_size: (size as u64)
+        };
+
+        let _response = self.unary(message, "SystemSharedMemoryRegister", |mut client, request| async move { client.system_shared_memory_register(request).await }).await?;
+        shm_handle.set_registration(Registration::new(self.clone(), SharedMemoryKind::System));
+
+        Ok(shm_handle)
+    }
+
+    /// Creates and registers `region_count` regions of `region_size` bytes, named
+    /// `{name_prefix}_{index}` with the keys `/{name_prefix}_{index}`, and pools them.
+    pub async fn create_system_shared_memory_pool(&self, name_prefix: &str, region_count: usize, region_size: usize) -> Result<SystemSharedMemoryPool, TritonError> {
+
+        let mut regions = Vec::with_capacity(region_count);
+        for index in 0..region_count {
+            let name = format!("{}_{}", name_prefix, index);
+            regions.push(self.create_system_shared_memory(&name, &format!("/{}", name), region_size).await?);
+        }
+
+        Ok(SystemSharedMemoryPool::from_regions(regions))
+    }
+
+    pub async fn system_shared_memory_status(&self, name: &str) -> Result<SystemSharedMemoryStatusResponse,  TritonError> {
+
+        let message = SystemSharedMemoryStatusRequest {
+            name: name.to_string()
+        };
+
+        let response = self.unary(message, "SystemSharedMemoryStatus", |mut client, request| async move { client.system_shared_memory_status(request).await }).await?;
+
+        Ok(response.into_inner())
+    }
+
+    pub async fn unregister_system_shared_memory(&self, name: &str) -> Result<SystemSharedMemoryUnregisterResponse,  TritonError> {
+
+        let message = SystemSharedMemoryUnregisterRequest {
+            name: name.to_string()
+        };
+
+        let response = self.unary(message, "SystemSharedMemoryUnregister", |mut client, request| async move { client.system_shared_memory_unregister(request).await }).await?;
+
+        Ok(response.into_inner())
+    }
+
+    pub fn get_system_shared_memory_params(&self, name: &str, size: u64, offset: u64) -> Result<HashMap<String, InferParameter>, TritonError> {
+        request::shared_memory_parameters(name, offset, size)
+    }
+}
+
+pub(crate) fn parse_metadata_key(key: &str) -> Result<AsciiMetadataKey, TritonError> {
+    key.parse().map_err(|_| TritonError::InvalidArgument(format!("invalid metadata key '{}'", key)))
+}
+
+pub(crate) fn parse_metadata_value(value: &str) -> Result<AsciiMetadataValue, TritonError> {
+    value.parse().map_err(|_| TritonError::InvalidArgument("invalid metadata value".to_string()))
+}
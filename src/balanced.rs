@@ -0,0 +1,439 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use tokio::runtime::Runtime;
+
+use crate::async_client::AsyncTritonClient;
+use crate::builder::ClientBuilder;
+use crate::error::TritonError;
+use crate::inference::ModelInferRequest;
+use crate::request::InferRequest;
+use crate::response::InferResponse;
+
+/// How `BalancedClient` picks the replica of a request among the healthy ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BalanceStrategy {
+    RoundRobin,
+    /// The replica with the fewest requests in flight, in round-robin order on ties.
+    LeastOutstanding,
+}
+
+/// State of one replica of a `BalancedClient`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointStatus {
+    pub address: String,
+    pub healthy: bool,
+    pub outstanding: usize
+}
+
+struct Replica {
+    address: String,
+    client: AsyncTritonClient,
+    healthy: AtomicBool,
+    outstanding: AtomicUsize
+}
+
+struct Shared {
+    replicas: Vec<Replica>,
+    strategy: BalanceStrategy,
+    probe_models: Vec<(String, String)>,
+    probe_timeout: Duration,
+    next: AtomicUsize
+}
+
+impl Replica {
+    fn record_probe(&self, healthy: bool) {
+        self.healthy.store(healthy, Ordering::SeqCst);
+    }
+
+    // Ejects the replica if the failure shows that it cannot serve requests
+    fn record_failure(&self, err: &TritonError) {
+        if matches!(err.code(), Some(tonic::Code::Unavailable)) || matches!(err, TritonError::Transport(_)) {
+            self.healthy.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+// Index of the replica to use among `count`, `load` returns the requests in flight of the
+// healthy replicas and `None` for the others. `start` is the round-robin position.
+fn select<F: Fn(usize) -> Option<usize>>(strategy: BalanceStrategy, start: usize, count: usize, load: F) -> Option<usize> {
+    let healthy = (0..count)
+        .map(|offset| (start % count + offset) % count)
+        .filter_map(|index| load(index).map(|outstanding| (index, outstanding)));
+
+    let selected = match strategy {
+        BalanceStrategy::RoundRobin => healthy.into_iter().next(),
+        // min_by_key keeps the first minimum, i.e. the next one in round-robin order
+        BalanceStrategy::LeastOutstanding => healthy.min_by_key(|(_, outstanding)| *outstanding),
+    };
+
+    selected.map(|(index, _)| index)
+}
+
+// Decrements the outstanding requests of a replica when the request completes or is cancelled
+struct OutstandingGuard<'a> {
+    replica: &'a Replica
+}
+
+impl Drop for OutstandingGuard<'_> {
+    fn drop(&mut self) {
+        self.replica.outstanding.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub struct BalancedClientBuilder {
+    endpoints: Vec<ClientBuilder>,
+    strategy: BalanceStrategy,
+    probe_interval: Duration,
+    probe_timeout: Duration,
+    probe_models: Vec<(String, String)>
+}
+
+impl BalancedClientBuilder {
+    pub fn new() -> Self {
+        BalancedClientBuilder {
+            endpoints: Vec::new(),
+            strategy: BalanceStrategy::RoundRobin,
+            probe_interval: Duration::from_secs(5),
+            probe_timeout: Duration::from_secs(2),
+            probe_models: Vec::new(),
+        }
+    }
+
+    /// Adds a replica, with its own connection options.
+    pub fn endpoint(mut self, endpoint: ClientBuilder) -> Self {
+        self.endpoints.push(endpoint);
+        self
+    }
+
+    /// Adds a replica with the default connection options.
+    pub fn address<A: Into<String>>(self, address: A) -> Self {
+        self.endpoint(ClientBuilder::new(address))
+    }
+
+    pub fn strategy(mut self, strategy: BalanceStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Interval between two health probes of every replica, 5 seconds by default.
+    pub fn probe_interval(mut self, interval: Duration) -> Self {
+        self.probe_interval = interval;
+        self
+    }
+
+    /// Time after which a probe without answer marks the replica unhealthy, 2 seconds by default.
+    pub fn probe_timeout(mut self, timeout: Duration) -> Self {
+        self.probe_timeout = timeout;
+        self
+    }
+
+    /// Model which has to be ready (`ModelReady`) for a replica to be healthy, in addition
+    /// to the server (`ServerReady`). An empty version means the server policy.
+    pub fn probe_model(mut self, model_name: &str, model_version: &str) -> Self {
+        self.probe_models.push((model_name.to_string(), model_version.to_string()));
+        self
+    }
+
+    /// Probes every replica once and starts probing them in the background.
+    ///
+    /// Replicas which cannot be reached are ejected, the call only fails if an
+    /// endpoint is invalid.
+    pub async fn connect(self) -> Result<BalancedClient, TritonError> {
+        if self.endpoints.is_empty() {
+            return Err(TritonError::InvalidArgument("a balanced client needs at least one endpoint".to_string()));
+        }
+
+        let mut replicas = Vec::new();
+        for endpoint in self.endpoints {
+            replicas.push(Replica {
+                address: endpoint.address().to_string(),
+                client: endpoint.connect_lazy()?,
+                healthy: AtomicBool::new(false),
+                outstanding: AtomicUsize::new(0),
+            });
+        }
+
+        let shared = Arc::new(Shared { replicas, strategy: self.strategy, probe_models: self.probe_models, probe_timeout: self.probe_timeout, next: AtomicUsize::new(0) });
+        shared.probe().await;
+        tokio::spawn(probe_periodically(Arc::downgrade(&shared), self.probe_interval));
+
+        Ok(BalancedClient { shared })
+    }
+
+    /// Connects a blocking balanced client, running on its own tokio runtime.
+    pub fn connect_blocking(self) -> Result<BlockingBalancedClient, TritonError> {
+        let rt = Runtime::new()?;
+        let client = rt.block_on(self.connect())?;
+
+        Ok(BlockingBalancedClient { rt, client })
+    }
+}
+
+impl Default for BalancedClientBuilder {
+    fn default() -> Self {
+        BalancedClientBuilder::new()
+    }
+}
+
+// Stops when every clone of the client has been dropped
+async fn probe_periodically(shared: Weak<Shared>, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+
+        match shared.upgrade() {
+            Some(shared) => shared.probe().await,
+            None => return,
+        }
+    }
+}
+
+impl Shared {
+    // Replicas are probed concurrently so that an unresponsive one does not delay the others
+    async fn probe(self: &Arc<Self>) {
+        let probes: Vec<_> = (0..self.replicas.len()).map(|index| {
+            let shared = self.clone();
+            tokio::spawn(async move {
+                let replica = &shared.replicas[index];
+                let healthy = matches!(tokio::time::timeout(shared.probe_timeout, shared.probe_replica(replica)).await, Ok(true));
+                replica.record_probe(healthy);
+            })
+        }).collect();
+
+        for probe in probes {
+            let _ = probe.await;
+        }
+    }
+
+    async fn probe_replica(&self, replica: &Replica) -> bool {
+        if !matches!(replica.client.is_server_ready().await, Ok(true)) {
+            return false;
+        }
+
+        for (model_name, model_version) in &self.probe_models {
+            if !matches!(replica.client.is_model_ready(model_name, model_version).await, Ok(true)) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn pick(&self) -> Result<&Replica, TritonError> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let index = select(self.strategy, start, self.replicas.len(), |index| {
+            let replica = &self.replicas[index];
+            replica.healthy.load(Ordering::SeqCst).then(|| replica.outstanding.load(Ordering::SeqCst))
+        });
+
+        index.map(|index| &self.replicas[index]).ok_or_else(|| tonic::Status::unavailable("no healthy Triton endpoint").into())
+    }
+}
+
+/// Client spreading inference requests over several replicas of a Triton server.
+///
+/// Every replica is probed periodically with `ServerReady` and `ModelReady` for the
+/// models given to `probe_model`. Unhealthy replicas are ejected until a probe
+/// succeeds again, and a replica is also ejected as soon as a request fails because
+/// it is unreachable. Requests are not resent to another replica, use a
+/// `RetryPolicy` on the endpoints for this.
+///
+/// The client is cheap to clone, clones share the replicas and their state.
+#[derive(Clone)]
+pub struct BalancedClient {
+    shared: Arc<Shared>
+}
+
+impl BalancedClient {
+    pub fn builder() -> BalancedClientBuilder {
+        BalancedClientBuilder::new()
+    }
+
+    pub async fn infer(&self, request: InferRequest) -> Result<InferResponse, TritonError> {
+        self.model_infer(request.build()?).await
+    }
+
+    pub async fn model_infer(&self, request: ModelInferRequest) -> Result<InferResponse, TritonError> {
+        let replica = self.shared.pick()?;
+
+        replica.outstanding.fetch_add(1, Ordering::SeqCst);
+        let _guard = OutstandingGuard { replica };
+
+        let response = replica.client.model_infer(request).await;
+        if let Err(err) = &response {
+            replica.record_failure(err);
+        }
+
+        response
+    }
+
+    /// Returns the client of a healthy replica chosen with the balancing strategy, e.g.
+    /// to send all the requests of a sequence to the same replica.
+    pub fn pick(&self) -> Result<AsyncTritonClient, TritonError> {
+        Ok(self.shared.pick()?.client.clone())
+    }
+
+    /// Probes every replica now, without waiting for the next periodic probe.
+    pub async fn probe(&self) {
+        self.shared.probe().await
+    }
+
+    pub fn endpoints(&self) -> Vec<EndpointStatus> {
+        self.shared.replicas.iter().map(|replica| EndpointStatus {
+            address: replica.address.clone(),
+            healthy: replica.healthy.load(Ordering::SeqCst),
+            outstanding: replica.outstanding.load(Ordering::SeqCst),
+        }).collect()
+    }
+}
+
+/// Blocking version of `BalancedClient`, the probes run on its runtime.
+pub struct BlockingBalancedClient {
+    rt: Runtime,
+    client: BalancedClient
+}
+
+impl BlockingBalancedClient {
+    pub fn async_client(&self) -> BalancedClient {
+        self.client.clone()
+    }
+
+    pub fn infer(&mut self, request: InferRequest) -> Result<InferResponse, TritonError> {
+        self.rt.block_on(self.client.infer(request))
+    }
+
+    pub fn model_infer(&mut self, request: ModelInferRequest) -> Result<InferResponse, TritonError> {
+        self.rt.block_on(self.client.model_infer(request))
+    }
+
+    pub fn probe(&mut self) {
+        self.rt.block_on(self.client.probe())
+    }
+
+    pub fn endpoints(&self) -> Vec<EndpointStatus> {
+        self.client.endpoints()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn picks(strategy: BalanceStrategy, starts: std::ops::Range<usize>, replicas: &[Option<usize>]) -> Vec<Option<usize>> {
+        starts.map(|start| select(strategy, start, replicas.len(), |index| replicas[index])).collect()
+    }
+
+    #[test]
+    fn round_robin() {
+        assert_eq!(picks(BalanceStrategy::RoundRobin, 0..5, &[Some(0), Some(5), Some(0)]), [Some(0), Some(1), Some(2), Some(0), Some(1)]);
+
+        // Unhealthy replicas are skipped, the next healthy one is used
+        assert_eq!(picks(BalanceStrategy::RoundRobin, 0..4, &[Some(0), None, Some(0), None]), [Some(0), Some(2), Some(2), Some(0)]);
+
+        // No overflow when the position wraps around
+        assert_eq!(select(BalanceStrategy::RoundRobin, usize::MAX, 3, |_| Some(0)), Some(usize::MAX % 3));
+    }
+
+    #[test]
+    fn least_outstanding() {
+        assert_eq!(picks(BalanceStrategy::LeastOutstanding, 0..3, &[Some(3), Some(1), Some(2)]), [Some(1), Some(1), Some(1)]);
+
+        // Ties are broken in round-robin order
+        assert_eq!(picks(BalanceStrategy::LeastOutstanding, 0..4, &[Some(1), Some(1), Some(4)]), [Some(0), Some(1), Some(0), Some(0)]);
+
+        // An idle unhealthy replica is not picked
+        assert_eq!(picks(BalanceStrategy::LeastOutstanding, 0..3, &[Some(2), None, Some(3)]), [Some(0), Some(0), Some(0)]);
+    }
+
+    #[test]
+    fn no_healthy_replica() {
+        for strategy in [BalanceStrategy::RoundRobin, BalanceStrategy::LeastOutstanding] {
+            assert_eq!(picks(strategy, 0..2, &[None, None]), [None, None]);
+            assert_eq!(select(strategy, 0, 0, |_| Some(0)), None);
+        }
+    }
+
+    // Lazy clients never connect, no server is needed
+    fn shared(count: usize, strategy: BalanceStrategy) -> Shared {
+        let replicas = (0..count).map(|index| Replica {
+            address: format!("http://127.0.0.1:{}", 9000 + index),
+            client: ClientBuilder::new(format!("http://127.0.0.1:{}", 9000 + index)).connect_lazy().unwrap(),
+            healthy: AtomicBool::new(true),
+            outstanding: AtomicUsize::new(0),
+        }).collect();
+
+        Shared { replicas, strategy, probe_models: Vec::new(), probe_timeout: Duration::from_secs(1), next: AtomicUsize::new(0) }
+    }
+
+    fn picked_addresses(shared: &Shared, count: usize) -> Vec<String> {
+        (0..count).map(|_| shared.pick().map(|replica| replica.address.clone()).unwrap_or_default()).collect()
+    }
+
+    #[tokio::test]
+    async fn ejection_and_readmission() {
+        let shared = shared(2, BalanceStrategy::RoundRobin);
+        assert_eq!(picked_addresses(&shared, 2), ["http://127.0.0.1:9000", "http://127.0.0.1:9001"]);
+
+        // Errors of the request itself keep the replica
+        shared.replicas[0].record_failure(&tonic::Status::invalid_argument("bad input").into());
+        shared.replicas[0].record_failure(&TritonError::Shape("bad shape".to_string()));
+        assert!(shared.replicas[0].healthy.load(Ordering::SeqCst));
+
+        shared.replicas[0].record_failure(&tonic::Status::unavailable("shutting down").into());
+        assert_eq!(picked_addresses(&shared, 2), ["http://127.0.0.1:9001", "http://127.0.0.1:9001"]);
+
+        shared.replicas[1].record_probe(false);
+        assert!(matches!(shared.pick(), Err(err) if err.code() == Some(tonic::Code::Unavailable)));
+
+        shared.replicas[0].record_probe(true);
+        assert_eq!(picked_addresses(&shared, 2), ["http://127.0.0.1:9000", "http://127.0.0.1:9000"]);
+    }
+
+    #[tokio::test]
+    async fn outstanding_requests() {
+        let shared = shared(3, BalanceStrategy::LeastOutstanding);
+
+        let first = shared.pick().unwrap();
+        first.outstanding.fetch_add(1, Ordering::SeqCst);
+        let guard = OutstandingGuard { replica: first };
+
+        let second = shared.pick().unwrap();
+        assert_ne!(first.address, second.address);
+
+        drop(guard);
+        assert_eq!(first.outstanding.load(Ordering::SeqCst), 0);
+    }
+}
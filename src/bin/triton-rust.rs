@@ -0,0 +1,74 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+
+use std::env;
+use std::process::ExitCode;
+
+use triton_rust::validate_repository;
+
+const USAGE: &str = "usage: triton-rust validate <model_repository>";
+
+fn validate(repository: &str) -> ExitCode {
+    match validate_repository(repository) {
+        Ok(issues) if issues.is_empty() => {
+            println!("{}: ok", repository);
+            ExitCode::SUCCESS
+        }
+        Ok(issues) => {
+            for issue in &issues {
+                println!("{}", issue);
+            }
+            println!("{} issue(s) found", issues.len());
+            ExitCode::FAILURE
+        }
+        Err(err) => {
+            eprintln!("cannot read {}: {}", repository, err);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    match args.iter().map(|arg| arg.as_str()).collect::<Vec<_>>().as_slice() {
+        ["validate", repository] => validate(repository),
+        ["-h"] | ["--help"] | ["help"] => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,295 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tonic::metadata::MetadataMap;
+use tonic::service::Interceptor;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
+use tokio::runtime::Runtime;
+
+use crate::inference::grpc_inference_service_client::GrpcInferenceServiceClient;
+use crate::async_client::{AsyncTritonClient, parse_metadata_key, parse_metadata_value};
+use crate::error::TritonError;
+use crate::retry::RetryPolicy;
+use crate::TritonInference;
+
+/// Connection options of the Triton clients.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use triton_rust::ClientBuilder;
+/// let client = ClientBuilder::new("http://127.0.0.1:8001")
+///     .connect_timeout(Duration::from_secs(5))
+///     .max_decoding_message_size(64 * 1024 * 1024)
+///     .connect_blocking()?;
+/// # Ok::<(), triton_rust::TritonError>(())
+/// ```
+#[derive(Clone)]
+pub struct ClientBuilder {
+    address: String,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    tcp_keepalive: Option<Duration>,
+    tcp_nodelay: bool,
+    http2_keep_alive_interval: Option<Duration>,
+    keep_alive_timeout: Option<Duration>,
+    keep_alive_while_idle: bool,
+    user_agent: Option<String>,
+    max_decoding_message_size: Option<usize>,
+    max_encoding_message_size: Option<usize>,
+    ca_certificate: Option<Vec<u8>>,
+    identity: Option<(Vec<u8>, Vec<u8>)>,
+    tls_domain_name: Option<String>,
+    headers: Vec<(String, String)>,
+    interceptor: Option<Arc<Mutex<dyn Interceptor + Send>>>,
+    retry_policy: Option<RetryPolicy>
+}
+
+impl ClientBuilder {
+    /// Address of the gRPC endpoint, e.g. `http://127.0.0.1:8001`. TLS is used for
+    /// `https://` addresses or as soon as a certificate is configured.
+    pub fn new<A: Into<String>>(address: A) -> Self {
+        ClientBuilder {
+            address: address.into(),
+            connect_timeout: None,
+            timeout: None,
+            tcp_keepalive: None,
+            tcp_nodelay: true,
+            http2_keep_alive_interval: None,
+            keep_alive_timeout: None,
+            keep_alive_while_idle: false,
+            user_agent: None,
+            max_decoding_message_size: None,
+            max_encoding_message_size: None,
+            ca_certificate: None,
+            identity: None,
+            tls_domain_name: None,
+            headers: Vec::new(),
+            interceptor: None,
+            retry_policy: None,
+        }
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Deadline applied to every request sent on the channel.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn tcp_keepalive(mut self, keepalive: Option<Duration>) -> Self {
+        self.tcp_keepalive = keepalive;
+        self
+    }
+
+    pub fn tcp_nodelay(mut self, enabled: bool) -> Self {
+        self.tcp_nodelay = enabled;
+        self
+    }
+
+    /// Interval of the HTTP/2 keepalive pings.
+    pub fn http2_keep_alive_interval(mut self, interval: Duration) -> Self {
+        self.http2_keep_alive_interval = Some(interval);
+        self
+    }
+
+    /// Time to wait for a keepalive acknowledgement before closing the connection.
+    pub fn keep_alive_timeout(mut self, timeout: Duration) -> Self {
+        self.keep_alive_timeout = Some(timeout);
+        self
+    }
+
+    pub fn keep_alive_while_idle(mut self, enabled: bool) -> Self {
+        self.keep_alive_while_idle = enabled;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Maximum size of a response message, 4 MB by default.
+    pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+        self.max_decoding_message_size = Some(limit);
+        self
+    }
+
+    /// Maximum size of a request message, unlimited by default.
+    pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+        self.max_encoding_message_size = Some(limit);
+        self
+    }
+
+    /// PEM encoded certificate of the authority used to verify the server.
+    pub fn ca_certificate<C: AsRef<[u8]>>(mut self, pem: C) -> Self {
+        self.ca_certificate = Some(pem.as_ref().to_vec());
+        self
+    }
+
+    /// PEM encoded client certificate and private key for mutual TLS.
+    pub fn identity<C: AsRef<[u8]>, K: AsRef<[u8]>>(mut self, certificate_pem: C, key_pem: K) -> Self {
+        self.identity = Some((certificate_pem.as_ref().to_vec(), key_pem.as_ref().to_vec()));
+        self
+    }
+
+    /// Name checked against the server certificate, defaults to the host of the address.
+    pub fn tls_domain_name(mut self, domain_name: &str) -> Self {
+        self.tls_domain_name = Some(domain_name.to_string());
+        self
+    }
+
+    /// Header sent with every request, e.g. a tenant identifier.
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        self.headers.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sends `authorization: Bearer <token>` with every request. Use `interceptor`
+    /// for tokens which have to be refreshed.
+    pub fn bearer_token(self, token: &str) -> Self {
+        self.header("authorization", &format!("Bearer {}", token))
+    }
+
+    /// Interceptor called on every request, after the headers are set.
+    pub fn interceptor<I: Interceptor + Send + 'static>(mut self, interceptor: I) -> Self {
+        self.interceptor = Some(Arc::new(Mutex::new(interceptor)));
+        self
+    }
+
+    /// Retries failed calls, see `RetryPolicy`. Calls are not retried by default.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    fn uses_tls(&self) -> bool {
+        self.address.starts_with("https://") || self.ca_certificate.is_some() || self.identity.is_some() || self.tls_domain_name.is_some()
+    }
+
+    /// The tonic endpoint configured with the connection options.
+    pub fn endpoint(&self) -> Result<Endpoint, TritonError> {
+        let mut endpoint = Endpoint::from_shared(self.address.clone())?
+            .tcp_keepalive(self.tcp_keepalive)
+            .tcp_nodelay(self.tcp_nodelay)
+            .keep_alive_while_idle(self.keep_alive_while_idle);
+
+        if let Some(timeout) = self.connect_timeout {
+            endpoint = endpoint.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            endpoint = endpoint.timeout(timeout);
+        }
+        if let Some(interval) = self.http2_keep_alive_interval {
+            endpoint = endpoint.http2_keep_alive_interval(interval);
+        }
+        if let Some(timeout) = self.keep_alive_timeout {
+            endpoint = endpoint.keep_alive_timeout(timeout);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            endpoint = endpoint.user_agent(user_agent.as_str())?;
+        }
+
+        if self.uses_tls() {
+            let mut tls = ClientTlsConfig::new();
+            if let Some(pem) = &self.ca_certificate {
+                tls = tls.ca_certificate(Certificate::from_pem(pem));
+            }
+            if let Some((certificate, key)) = &self.identity {
+                tls = tls.identity(Identity::from_pem(certificate, key));
+            }
+            if let Some(domain_name) = &self.tls_domain_name {
+                tls = tls.domain_name(domain_name.as_str());
+            }
+            endpoint = endpoint.tls_config(tls)?;
+        }
+
+        Ok(endpoint)
+    }
+
+    pub async fn connect(self) -> Result<AsyncTritonClient, TritonError> {
+        let channel = self.endpoint()?.connect().await?;
+
+        self.into_client(channel)
+    }
+
+    /// Returns a client which connects on its first request, reconnecting whenever the
+    /// connection is lost. It has to be called from a tokio runtime.
+    pub fn connect_lazy(self) -> Result<AsyncTritonClient, TritonError> {
+        let channel = self.endpoint()?.connect_lazy();
+
+        self.into_client(channel)
+    }
+
+    fn into_client(self, channel: Channel) -> Result<AsyncTritonClient, TritonError> {
+        let mut client = GrpcInferenceServiceClient::new(channel);
+        if let Some(limit) = self.max_decoding_message_size {
+            client = client.max_decoding_message_size(limit);
+        }
+        if let Some(limit) = self.max_encoding_message_size {
+            client = client.max_encoding_message_size(limit);
+        }
+
+        let mut metadata = MetadataMap::new();
+        for (key, value) in &self.headers {
+            metadata.append(parse_metadata_key(key)?, parse_metadata_value(value)?);
+        }
+
+        let mut client = AsyncTritonClient::from_client(client).with_metadata(&metadata);
+        if let Some(interceptor) = self.interceptor {
+            client = client.with_shared_interceptor(interceptor);
+        }
+        if let Some(policy) = self.retry_policy {
+            client = client.with_retry_policy(policy);
+        }
+
+        Ok(client)
+    }
+
+    /// Connects a blocking client, running on its own tokio runtime.
+    pub fn connect_blocking(self) -> Result<TritonInference, TritonError> {
+        let rt = Runtime::new()?;
+        let client = rt.block_on(self.connect())?;
+
+        Ok(TritonInference::from_parts(rt, client))
+    }
+}
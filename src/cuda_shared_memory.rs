@@ -33,75 +33,157 @@ knowledge of the CeCILL-B license and that you accept its terms.*/
 #![allow(non_snake_case)]
 #![allow(dead_code)]
 
-use std::ffi::{CString};
-use std::os::raw::{c_void, c_char};
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_void, c_char, c_int};
+
+use crate::error::TritonError;
+use crate::registration::Registration;
 
 include!(concat!(env!("OUT_DIR"), "/shared_memory_binding.rs"));
 
+extern "C" {
+    // The serialized raw handle is allocated with malloc by the shared memory library
+    fn free(ptr: *mut c_void);
+}
+
+/// CUDA memory region registered to Triton as CUDA shared memory.
+///
+/// Dropping the handle frees the region and, for regions created by a client, unregisters
+/// it from the server (best-effort, errors are ignored). Inside an async context the
+/// unregistration is only started on drop, use `unregister` to wait for it. Use `leak` or
+/// `into_raw` to keep the region alive.
 pub struct CudaSharedMemoryRegionHandle {
     name: String,
-    handle: *mut c_void
+    handle: *mut c_void,
+    registration: Option<Registration>
+}
+
+// The handle only points to a heap allocated region descriptor, it can be moved
+// to another thread (e.g. across an await point of the async client).
+unsafe impl Send for CudaSharedMemoryRegionHandle {}
+
+fn check_result(operation: &'static str, code: c_int) -> Result<(), TritonError> {
+    if code != 0 {
+        return Err(TritonError::SharedMemory { operation, code });
+    }
+
+    Ok(())
 }
 
 impl CudaSharedMemoryRegionHandle {
-    pub fn create(triton_shm_name: &'static str, size: usize, device_id: i64) -> Self {
+    pub fn create(triton_shm_name: &str, size: usize, device_id: i64) -> Result<Self, TritonError> {
 
-        let c_triton_shm_name = CString::new(triton_shm_name).unwrap();
+        let c_triton_shm_name = CString::new(triton_shm_name)?;
+        let c_device_id = c_int::try_from(device_id).map_err(|_| TritonError::InvalidArgument(format!("invalid CUDA device id {}", device_id)))?;
         let mut handle: *mut c_void = std::ptr::null_mut();
 
-        let _result = unsafe {
+        let result = unsafe {
             CudaSharedMemoryRegionCreate(
                 c_triton_shm_name.as_ptr(),
                 size,
-                device_id.try_into().unwrap(),
+                c_device_id,
                 &mut handle
             )
         };
+        check_result("CudaSharedMemoryRegionCreate", result)?;
 
-        CudaSharedMemoryRegionHandle {
+        Ok(CudaSharedMemoryRegionHandle {
             name: triton_shm_name.to_string(),
-            handle: handle,
-        }
+            handle,
+            registration: None,
+        })
     }
 
-    pub fn from_ptr(triton_shm_name: &'static str, ptr: *mut c_void) -> Self {
+    /// Takes the ownership of a region descriptor of the shared memory library, e.g.
+    /// returned by `into_raw`.
+    pub fn from_ptr(triton_shm_name: &str, ptr: *mut c_void) -> Self {
 
         CudaSharedMemoryRegionHandle {
             name: triton_shm_name.to_string(),
             handle: ptr,
+            registration: None,
+        }
+    }
+
+    pub(crate) fn set_registration(&mut self, registration: Registration) {
+        self.registration = Some(registration);
+    }
+
+    pub(crate) fn registration_mut(&mut self) -> Option<&mut Registration> {
+        self.registration.as_mut()
+    }
+
+    /// Unregisters the region from the server, waiting for the answer, then releases it as
+    /// on drop. Does nothing for a region that is not registered by a client.
+    pub async fn unregister(mut self) -> Result<(), TritonError> {
+        let name = self.name.clone();
+        match self.registration.take() {
+            Some(registration) => registration.unregister(&name).await,
+            None => Ok(()),
         }
     }
 
+    /// Keeps the region and its registration on the server for the lifetime of the process.
+    pub fn leak(mut self) {
+        self.registration = None;
+        self.handle = std::ptr::null_mut();
+    }
+
+    /// Releases the ownership of the region descriptor. The caller becomes responsible
+    /// for destroying the region and unregistering it from the server.
+    pub fn into_raw(mut self) -> *mut c_void {
+        let handle = self.handle;
+        self.registration = None;
+        self.handle = std::ptr::null_mut();
+        handle
+    }
+
     pub fn get_name(&mut self) -> String {
         self.name.clone()
     }
 
-    pub fn get_raw_handle(&mut self) -> Vec<u8> {
+    pub fn get_raw_handle(&mut self) -> Result<Vec<u8>, TritonError> {
 
         let mut raw_handle_ptr: *mut c_char = std::ptr::null_mut();
 
-        let _result = unsafe {
+        let result = unsafe {
             CudaSharedMemoryGetRawHandle(
                 self.handle,
                 &mut raw_handle_ptr
             )
         };
+        check_result("CudaSharedMemoryGetRawHandle", result)?;
 
-        let raw_handle_str = unsafe { CString::from_raw(raw_handle_ptr) };
-        println!("{:?}", raw_handle_str);
-        let raw_handle = raw_handle_str.into_bytes();
-        println!("{:?}", raw_handle);
-        println!("{:?}", raw_handle.len());
+        let raw_handle = unsafe { CStr::from_ptr(raw_handle_ptr) }.to_bytes().to_vec();
+        unsafe { free(raw_handle_ptr as *mut c_void) };
 
-        raw_handle
+        Ok(raw_handle)
     }
 
-    pub fn destroy(&mut self) {
+    /// Frees the region. It stays registered on the server until the handle is dropped.
+    pub fn destroy(&mut self) -> Result<(), TritonError> {
+        if self.handle.is_null() {
+            return Ok(());
+        }
 
-        let _result = unsafe {
+        let result = unsafe {
             CudaSharedMemoryRegionDestroy(
                 self.handle
             )
         };
+        check_result("CudaSharedMemoryRegionDestroy", result)?;
+        self.handle = std::ptr::null_mut();
+
+        Ok(())
+    }
+}
+
+impl Drop for CudaSharedMemoryRegionHandle {
+    fn drop(&mut self) {
+        if let Some(registration) = self.registration.take() {
+            registration.unregister_best_effort(self.name.clone());
+        }
+
+        let _ = self.destroy();
     }
 }
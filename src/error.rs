@@ -0,0 +1,126 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+use std::error::Error;
+use std::fmt;
+
+/// Errors returned by the Triton clients.
+#[derive(Debug)]
+pub enum TritonError {
+    /// The gRPC channel could not be established or was lost.
+    Transport(tonic::transport::Error),
+    /// The server answered with a non-OK gRPC status (model not found, invalid input, ...).
+    Status(Box<tonic::Status>),
+    /// The server reported an error for one request of an inference stream.
+    Inference { request_id: String, message: String },
+    /// A tensor shape is invalid or inconsistent with the tensor content.
+    Shape(String),
+    /// A datatype is unknown or does not match the expected element type.
+    DataType(String),
+    /// A call to the shared memory library returned an error code.
+    SharedMemory { operation: &'static str, code: i32 },
+    /// An argument cannot be sent to the server (e.g. a name containing a nul byte).
+    InvalidArgument(String),
+    /// An I/O error, e.g. when starting the runtime of the blocking client.
+    Io(std::io::Error),
+    /// A model configuration in text format could not be parsed.
+    Parse(String),
+    /// A call failed after several attempts, `error` is the failure of the last one.
+    Retried { attempts: u32, error: Box<TritonError> },
+    /// A shared memory pool has no contiguous free space for a slice of `requested` bytes,
+    /// `available` is the total free space of the pool.
+    PoolExhausted { requested: usize, available: usize },
+}
+
+impl TritonError {
+    /// Returns the gRPC status code if the error comes from the server.
+    pub fn code(&self) -> Option<tonic::Code> {
+        match self {
+            TritonError::Status(status) => Some(status.code()),
+            TritonError::Retried { error, .. } => error.code(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TritonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TritonError::Transport(err) => write!(f, "transport error: {}", err),
+            TritonError::Status(status) => write!(f, "server returned {:?}: {}", status.code(), status.message()),
+            TritonError::Inference { request_id, message } => write!(f, "inference request '{}' failed: {}", request_id, message),
+            TritonError::Shape(message) => write!(f, "invalid shape: {}", message),
+            TritonError::DataType(message) => write!(f, "invalid datatype: {}", message),
+            TritonError::SharedMemory { operation, code } => write!(f, "shared memory operation {} failed with code {}", operation, code),
+            TritonError::InvalidArgument(message) => write!(f, "invalid argument: {}", message),
+            TritonError::Io(err) => write!(f, "I/O error: {}", err),
+            TritonError::Parse(message) => write!(f, "parse error: {}", message),
+            TritonError::Retried { attempts, error } => write!(f, "failed after {} attempts: {}", attempts, error),
+            TritonError::PoolExhausted { requested, available } => write!(f, "no free block of {} bytes in the shared memory pool ({} bytes available)", requested, available),
+        }
+    }
+}
+
+impl Error for TritonError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TritonError::Transport(err) => Some(err),
+            TritonError::Status(status) => Some(status.as_ref()),
+            TritonError::Io(err) => Some(err),
+            TritonError::Retried { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<tonic::transport::Error> for TritonError {
+    fn from(err: tonic::transport::Error) -> Self {
+        TritonError::Transport(err)
+    }
+}
+
+impl From<tonic::Status> for TritonError {
+    fn from(status: tonic::Status) -> Self {
+        TritonError::Status(Box::new(status))
+    }
+}
+
+impl From<std::io::Error> for TritonError {
+    fn from(err: std::io::Error) -> Self {
+        TritonError::Io(err)
+    }
+}
+
+impl From<std::ffi::NulError> for TritonError {
+    fn from(err: std::ffi::NulError) -> Self {
+        TritonError::InvalidArgument(err.to_string())
+    }
+}
@@ -0,0 +1,261 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+use crate::async_client::AsyncTritonClient;
+use crate::error::TritonError;
+use crate::inference::{ModelConfig, ModelMetadataResponse};
+use crate::inference::model_metadata_response::TensorMetadata;
+use crate::request::InferRequest;
+use crate::response::InferResponse;
+use crate::sequence::Sequence;
+
+/// Handle on a model version with its metadata and configuration fetched once.
+///
+/// Requests sent through the handle are checked against the metadata (input names,
+/// datatypes and shapes, requested outputs) before leaving the process.
+#[derive(Clone)]
+pub struct Model {
+    client: AsyncTritonClient,
+    name: String,
+    version: String,
+    metadata: ModelMetadataResponse,
+    config: ModelConfig
+}
+
+impl Model {
+    pub(crate) async fn fetch(client: AsyncTritonClient, model_name: &str, model_version: &str) -> Result<Self, TritonError> {
+        let metadata = client.get_model_metadata(model_name, model_version).await?;
+        let config = client.get_model_config(model_name, model_version).await?;
+
+        Ok(Model {
+            client,
+            name: model_name.to_string(),
+            version: model_version.to_string(),
+            metadata,
+            config
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn metadata(&self) -> &ModelMetadataResponse {
+        &self.metadata
+    }
+
+    pub fn config(&self) -> &ModelConfig {
+        &self.config
+    }
+
+    pub fn inputs(&self) -> &[TensorMetadata] {
+        &self.metadata.inputs
+    }
+
+    pub fn outputs(&self) -> &[TensorMetadata] {
+        &self.metadata.outputs
+    }
+
+    pub fn input(&self, name: &str) -> Option<&TensorMetadata> {
+        self.metadata.inputs.iter().find(|input| input.name == name)
+    }
+
+    pub fn output(&self, name: &str) -> Option<&TensorMetadata> {
+        self.metadata.outputs.iter().find(|output| output.name == name)
+    }
+
+    /// Returns a new request for this model and version.
+    pub fn request(&self) -> InferRequest {
+        InferRequest::new(&self.name).version(&self.version)
+    }
+
+    fn is_optional_input(&self, name: &str) -> bool {
+        self.config.input.iter().any(|input| input.name == name && input.optional)
+    }
+
+    /// Checks a request against the model metadata, `-1` dimensions accept any size.
+    pub fn validate(&self, request: &InferRequest) -> Result<(), TritonError> {
+        if request.model_name() != self.name {
+            return Err(TritonError::InvalidArgument(format!("request is for model '{}', not '{}'", request.model_name(), self.name)));
+        }
+
+        if !request.model_version().is_empty() && request.model_version() != self.version {
+            return Err(TritonError::InvalidArgument(format!("request is for version '{}' of model '{}', not '{}'", request.model_version(), self.name, self.version)));
+        }
+
+        for (name, datatype, shape) in request.input_specs() {
+            let expected = self.input(name)
+                .ok_or_else(|| TritonError::InvalidArgument(format!("model '{}' has no input named '{}'", self.name, name)))?;
+
+            if datatype.as_str() != expected.datatype {
+                return Err(TritonError::DataType(format!("input '{}' of model '{}' expects {}, got {}", name, self.name, expected.datatype, datatype)));
+            }
+
+            let matches = shape.len() == expected.shape.len()
+                && shape.iter().zip(expected.shape.iter()).all(|(dim, expected_dim)| *expected_dim == -1 || dim == expected_dim);
+            if !matches {
+                return Err(TritonError::Shape(format!("input '{}' of model '{}' expects shape {:?}, got {:?}", name, self.name, expected.shape, shape)));
+            }
+        }
+
+        for input in &self.metadata.inputs {
+            if !self.is_optional_input(&input.name) && request.input_spec(&input.name).is_none() {
+                return Err(TritonError::InvalidArgument(format!("missing input '{}' of model '{}'", input.name, self.name)));
+            }
+        }
+
+        for name in request.output_names() {
+            if self.output(name).is_none() {
+                return Err(TritonError::InvalidArgument(format!("model '{}' has no output named '{}'", self.name, name)));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn is_ready(&self) -> Result<bool, TritonError> {
+        self.client.is_model_ready(&self.name, &self.version).await
+    }
+
+    pub async fn infer(&self, request: InferRequest) -> Result<InferResponse, TritonError> {
+        self.validate(&request)?;
+
+        self.client.infer(request).await
+    }
+
+    pub async fn infer_sequence(&self, sequence: &mut Sequence, request: InferRequest, last: bool) -> Result<InferResponse, TritonError> {
+        self.validate(&request)?;
+
+        self.client.infer_sequence(sequence, request, last).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::builder::ClientBuilder;
+    use crate::inference::ModelInput;
+
+    fn tensor(name: &str, datatype: &str, shape: &[i64]) -> TensorMetadata {
+        TensorMetadata { name: name.to_string(), datatype: datatype.to_string(), shape: shape.to_vec() }
+    }
+
+    // The lazy client never connects, validation does not use the network
+    fn model() -> Model {
+        let metadata = ModelMetadataResponse {
+            name: "bert".to_string(),
+            inputs: vec![tensor("input_ids", "INT64", &[-1, 128]), tensor("token_type_ids", "INT64", &[-1, 128])],
+            outputs: vec![tensor("logits", "FP32", &[-1, 2])],
+            ..Default::default()
+        };
+        let config = ModelConfig {
+            name: "bert".to_string(),
+            input: vec![
+                ModelInput { name: "input_ids".to_string(), ..Default::default() },
+                ModelInput { name: "token_type_ids".to_string(), optional: true, ..Default::default() },
+            ],
+            ..Default::default()
+        };
+
+        Model {
+            client: ClientBuilder::new("http://127.0.0.1:1").connect_lazy().unwrap(),
+            name: "bert".to_string(),
+            version: "1".to_string(),
+            metadata,
+            config
+        }
+    }
+
+    fn ids(batch_size: usize, length: usize) -> ndarray::Array2<i64> {
+        ndarray::Array2::zeros((batch_size, length))
+    }
+
+    #[tokio::test]
+    async fn valid_requests() {
+        let model = model();
+
+        // -1 accepts any batch size, the optional input can be omitted
+        model.validate(&model.request().input("input_ids", &ids(1, 128)).output("logits")).unwrap();
+        model.validate(&model.request().input("input_ids", &ids(8, 128)).input("token_type_ids", &ids(8, 128))).unwrap();
+
+        // Without a version the request matches the handle
+        model.validate(&InferRequest::new("bert").input("input_ids", &ids(2, 128))).unwrap();
+    }
+
+    #[tokio::test]
+    async fn wrong_model_or_version() {
+        let model = model();
+        assert!(matches!(model.validate(&InferRequest::new("gpt").input("input_ids", &ids(1, 128))), Err(TritonError::InvalidArgument(_))));
+        assert!(matches!(model.validate(&InferRequest::new("bert").version("2").input("input_ids", &ids(1, 128))), Err(TritonError::InvalidArgument(_))));
+    }
+
+    #[tokio::test]
+    async fn invalid_inputs() {
+        let model = model();
+
+        let request = model.request().input("input_ids", &ids(1, 128)).input("attention_mask", &ids(1, 128));
+        assert!(matches!(model.validate(&request), Err(TritonError::InvalidArgument(message)) if message.contains("no input named 'attention_mask'")));
+
+        let request = model.request().input("input_ids", &ndarray::Array2::<i32>::zeros((1, 128)));
+        assert!(matches!(model.validate(&request), Err(TritonError::DataType(_))));
+
+        // Fixed dimensions must match exactly
+        assert!(matches!(model.validate(&model.request().input("input_ids", &ids(1, 64))), Err(TritonError::Shape(_))));
+
+        // Rank mismatch, even with -1 dimensions
+        let request = model.request().input("input_ids", &ndarray::Array3::<i64>::zeros((1, 128, 1)));
+        assert!(matches!(model.validate(&request), Err(TritonError::Shape(_))));
+        let request = model.request().input("input_ids", &ndarray::Array1::<i64>::zeros(128));
+        assert!(matches!(model.validate(&request), Err(TritonError::Shape(_))));
+    }
+
+    #[tokio::test]
+    async fn missing_inputs() {
+        let model = model();
+
+        // Only the optional input is given
+        let request = model.request().input("token_type_ids", &ids(1, 128));
+        assert!(matches!(model.validate(&request), Err(TritonError::InvalidArgument(message)) if message.contains("missing input 'input_ids'")));
+    }
+
+    #[tokio::test]
+    async fn unknown_output() {
+        let model = model();
+
+        let request = model.request().input("input_ids", &ids(1, 128)).output("logits").output("hidden_states");
+        assert!(matches!(model.validate(&request), Err(TritonError::InvalidArgument(message)) if message.contains("no output named 'hidden_states'")));
+    }
+}
@@ -0,0 +1,1223 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use prost::Message;
+use prost_types::{DescriptorProto, EnumDescriptorProto, FieldDescriptorProto, FileDescriptorSet};
+use prost_types::field_descriptor_proto::{Label, Type};
+
+use crate::error::TritonError;
+use crate::inference::ModelConfig;
+
+// Descriptors of the inference protocol, written by tonic-build at compile time.
+// They drive a generic text-format codec so that every message of `model_config.proto`
+// is supported without hand-written conversions.
+static FILE_DESCRIPTOR_SET: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/inference_descriptor.bin"));
+
+const MODEL_CONFIG_MESSAGE: &str = ".inference.ModelConfig";
+
+struct Descriptors {
+    messages: HashMap<String, DescriptorProto>,
+    enums: HashMap<String, EnumDescriptorProto>
+}
+
+fn descriptors() -> Result<&'static Descriptors, TritonError> {
+    static DESCRIPTORS: OnceLock<Result<Descriptors, String>> = OnceLock::new();
+
+    let descriptors = DESCRIPTORS.get_or_init(|| {
+        let file_set = FileDescriptorSet::decode(FILE_DESCRIPTOR_SET).map_err(|err| format!("invalid inference file descriptor set: {}", err))?;
+        let mut descriptors = Descriptors { messages: HashMap::new(), enums: HashMap::new() };
+
+        for file in &file_set.file {
+            let package = format!(".{}", file.package());
+            for enum_type in &file.enum_type {
+                descriptors.enums.insert(format!("{}.{}", package, enum_type.name()), enum_type.clone());
+            }
+            for message in &file.message_type {
+                index_message(&package, message, &mut descriptors);
+            }
+        }
+
+        Ok(descriptors)
+    });
+
+    descriptors.as_ref().map_err(|message| TritonError::Parse(message.clone()))
+}
+
+fn index_message(scope: &str, message: &DescriptorProto, descriptors: &mut Descriptors) {
+    let full_name = format!("{}.{}", scope, message.name());
+
+    for enum_type in &message.enum_type {
+        descriptors.enums.insert(format!("{}.{}", full_name, enum_type.name()), enum_type.clone());
+    }
+    for nested in &message.nested_type {
+        index_message(&full_name, nested, descriptors);
+    }
+
+    descriptors.messages.insert(full_name, message.clone());
+}
+
+fn message_descriptor(type_name: &str) -> Result<&'static DescriptorProto, TritonError> {
+    descriptors()?.messages.get(type_name).ok_or_else(|| TritonError::Parse(format!("unknown message type {}", type_name)))
+}
+
+fn enum_descriptor(type_name: &str) -> Result<&'static EnumDescriptorProto, TritonError> {
+    descriptors()?.enums.get(type_name).ok_or_else(|| TritonError::Parse(format!("unknown enum type {}", type_name)))
+}
+
+fn is_map_entry(message: &DescriptorProto) -> bool {
+    message.options.as_ref().map(|options| options.map_entry()).unwrap_or(false)
+}
+
+fn is_repeated(field: &FieldDescriptorProto) -> bool {
+    field.label() == Label::Repeated
+}
+
+fn map_entry_descriptor(field: &FieldDescriptorProto) -> Result<Option<&'static DescriptorProto>, TritonError> {
+    if field.r#type() == Type::Message && is_repeated(field) {
+        let entry = message_descriptor(field.type_name())?;
+        if is_map_entry(entry) {
+            return Ok(Some(entry));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Parses a model configuration in protobuf text format (`config.pbtxt`).
+pub fn parse_model_config(text: &str) -> Result<ModelConfig, TritonError> {
+    let bytes = parse_text_format(MODEL_CONFIG_MESSAGE, text)?;
+
+    ModelConfig::decode(bytes.as_slice()).map_err(|err| TritonError::Parse(err.to_string()))
+}
+
+/// Serializes a model configuration in protobuf text format.
+///
+/// The output is deterministic: fields follow their declaration order in
+/// `model_config.proto` and map entries are sorted by key.
+pub fn model_config_to_pbtxt(config: &ModelConfig) -> Result<String, TritonError> {
+    let fields = decode_message(message_descriptor(MODEL_CONFIG_MESSAGE)?, &config.encode_to_vec())?;
+
+    let mut output = String::new();
+    write_text(&fields, 0, &mut output)?;
+    Ok(output)
+}
+
+/// Serializes a model configuration in JSON, the format expected by the `config`
+/// override of a model load request.
+pub fn model_config_to_json(config: &ModelConfig) -> Result<String, TritonError> {
+    let fields = decode_message(message_descriptor(MODEL_CONFIG_MESSAGE)?, &config.encode_to_vec())?;
+
+    let mut output = String::new();
+    write_json(&fields, 0, &mut output)?;
+    Ok(output)
+}
+
+pub fn read_model_config<P: AsRef<Path>>(path: P) -> Result<ModelConfig, TritonError> {
+    let text = fs::read_to_string(path.as_ref())?;
+
+    parse_model_config(&text).map_err(|err| match err {
+        TritonError::Parse(message) => TritonError::Parse(format!("{}: {}", path.as_ref().display(), message)),
+        err => err,
+    })
+}
+
+pub fn write_model_config<P: AsRef<Path>>(path: P, config: &ModelConfig) -> Result<(), TritonError> {
+    fs::write(path, model_config_to_pbtxt(config)?)?;
+
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Text format lexer
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Identifier(String),
+    Number(String),
+    String(Vec<u8>),
+    Punctuation(char),
+}
+
+struct Lexer<'a> {
+    input: &'a [u8],
+    position: usize,
+    line: usize,
+    peeked: Option<(Token, usize)>
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &'a str) -> Self {
+        Lexer { input: input.as_bytes(), position: 0, line: 1, peeked: None }
+    }
+
+    fn error(&self, message: String) -> TritonError {
+        TritonError::Parse(format!("line {}: {}", self.line, message))
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        while let Some(&byte) = self.input.get(self.position) {
+            match byte {
+                b'\n' => {
+                    self.line += 1;
+                    self.position += 1;
+                }
+                b' ' | b'\t' | b'\r' => self.position += 1,
+                b'#' => {
+                    while self.position < self.input.len() && self.input[self.position] != b'\n' {
+                        self.position += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<&Token>, TritonError> {
+        if self.peeked.is_none() {
+            self.peeked = self.read_token()?.map(|token| (token, self.line));
+        }
+
+        Ok(self.peeked.as_ref().map(|(token, _)| token))
+    }
+
+    fn next(&mut self) -> Result<Option<Token>, TritonError> {
+        self.peek()?;
+
+        Ok(self.peeked.take().map(|(token, _)| token))
+    }
+
+    fn next_is(&mut self, punctuation: char) -> Result<bool, TritonError> {
+        if self.peek()? == Some(&Token::Punctuation(punctuation)) {
+            self.next()?;
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    fn expect(&mut self, punctuation: char) -> Result<(), TritonError> {
+        match self.next()? {
+            Some(Token::Punctuation(found)) if found == punctuation => Ok(()),
+            found => Err(self.error(format!("expected '{}', found {:?}", punctuation, found))),
+        }
+    }
+
+    fn read_token(&mut self) -> Result<Option<Token>, TritonError> {
+        self.skip_whitespace_and_comments();
+
+        let start = self.position;
+        let byte = match self.input.get(start) {
+            Some(byte) => *byte,
+            None => return Ok(None),
+        };
+
+        if byte == b'"' || byte == b'\'' {
+            let mut value = self.read_string(byte)?;
+
+            // Adjacent string literals are concatenated
+            loop {
+                self.skip_whitespace_and_comments();
+                match self.input.get(self.position) {
+                    Some(&quote) if quote == b'"' || quote == b'\'' => value.extend(self.read_string(quote)?),
+                    _ => break,
+                }
+            }
+
+            return Ok(Some(Token::String(value)));
+        }
+
+        if byte.is_ascii_alphabetic() || byte == b'_' {
+            while self.position < self.input.len() && (self.input[self.position].is_ascii_alphanumeric() || self.input[self.position] == b'_') {
+                self.position += 1;
+            }
+
+            return Ok(Some(Token::Identifier(String::from_utf8_lossy(&self.input[start..self.position]).to_string())));
+        }
+
+        if byte.is_ascii_digit() || byte == b'-' || byte == b'+' || byte == b'.' {
+            self.position += 1;
+            while let Some(&next) = self.input.get(self.position) {
+                let previous = self.input[self.position - 1];
+                let exponent_sign = (next == b'-' || next == b'+') && (previous == b'e' || previous == b'E') && !self.input[start..self.position].starts_with(b"0x");
+
+                if next.is_ascii_alphanumeric() || next == b'.' || next == b'_' || exponent_sign {
+                    self.position += 1;
+                } else {
+                    break;
+                }
+            }
+
+            return Ok(Some(Token::Number(String::from_utf8_lossy(&self.input[start..self.position]).to_string())));
+        }
+
+        if b"{}[]<>:,;".contains(&byte) {
+            self.position += 1;
+            return Ok(Some(Token::Punctuation(byte as char)));
+        }
+
+        Err(self.error(format!("unexpected character '{}'", byte as char)))
+    }
+
+    fn read_string(&mut self, quote: u8) -> Result<Vec<u8>, TritonError> {
+        let mut value = Vec::new();
+        self.position += 1;
+
+        loop {
+            let byte = match self.input.get(self.position) {
+                Some(b'\n') | None => return Err(self.error("unterminated string".to_string())),
+                Some(byte) => *byte,
+            };
+            self.position += 1;
+
+            if byte == quote {
+                return Ok(value);
+            }
+
+            if byte != b'\\' {
+                value.push(byte);
+                continue;
+            }
+
+            let escaped = *self.input.get(self.position).ok_or_else(|| self.error("unterminated string".to_string()))?;
+            self.position += 1;
+
+            match escaped {
+                b'n' => value.push(b'\n'),
+                b'r' => value.push(b'\r'),
+                b't' => value.push(b'\t'),
+                b'a' => value.push(0x07),
+                b'b' => value.push(0x08),
+                b'f' => value.push(0x0c),
+                b'v' => value.push(0x0b),
+                b'0'..=b'7' => {
+                    let mut code = (escaped - b'0') as u32;
+                    for _ in 0..2 {
+                        match self.input.get(self.position) {
+                            Some(digit @ b'0'..=b'7') => {
+                                code = code * 8 + (digit - b'0') as u32;
+                                self.position += 1;
+                            }
+                            _ => break,
+                        }
+                    }
+                    value.push(code as u8);
+                }
+                b'x' => {
+                    let mut code = 0u32;
+                    let mut digits = 0;
+                    while let Some(digit) = self.input.get(self.position).and_then(|byte| (*byte as char).to_digit(16)) {
+                        if digits == 2 {
+                            break;
+                        }
+                        code = code * 16 + digit;
+                        digits += 1;
+                        self.position += 1;
+                    }
+                    if digits == 0 {
+                        return Err(self.error("invalid hexadecimal escape".to_string()));
+                    }
+                    value.push(code as u8);
+                }
+                other => value.push(other),
+            }
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Text format parser, producing the protobuf binary encoding of the message
+
+const WIRE_VARINT: u32 = 0;
+const WIRE_FIXED64: u32 = 1;
+const WIRE_LENGTH_DELIMITED: u32 = 2;
+const WIRE_FIXED32: u32 = 5;
+
+fn put_varint(buffer: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buffer.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buffer.push(value as u8);
+}
+
+fn put_key(buffer: &mut Vec<u8>, number: i32, wire_type: u32) {
+    put_varint(buffer, ((number as u64) << 3) | wire_type as u64);
+}
+
+fn put_length_delimited(buffer: &mut Vec<u8>, number: i32, content: &[u8]) {
+    put_key(buffer, number, WIRE_LENGTH_DELIMITED);
+    put_varint(buffer, content.len() as u64);
+    buffer.extend_from_slice(content);
+}
+
+fn parse_text_format(message_name: &str, text: &str) -> Result<Vec<u8>, TritonError> {
+    let mut lexer = Lexer::new(text);
+
+    parse_message(&mut lexer, message_descriptor(message_name)?, None)
+}
+
+fn parse_message(lexer: &mut Lexer, message: &DescriptorProto, end: Option<char>) -> Result<Vec<u8>, TritonError> {
+    let mut buffer = Vec::new();
+    let mut seen = Vec::new();
+
+    loop {
+        let field_name = match lexer.next()? {
+            None if end.is_none() => break,
+            None => return Err(lexer.error(format!("missing closing '{}' in {}", end.unwrap(), message.name()))),
+            Some(Token::Punctuation(found)) if Some(found) == end => break,
+            Some(Token::Identifier(name)) => name,
+            Some(token) => return Err(lexer.error(format!("expected a field name, found {:?}", token))),
+        };
+
+        let field = message.field.iter().find(|field| field.name() == field_name)
+            .ok_or_else(|| lexer.error(format!("message {} has no field named '{}'", message.name(), field_name)))?;
+
+        if !is_repeated(field) {
+            if seen.contains(&field.number()) {
+                return Err(lexer.error(format!("non-repeated field '{}' is specified multiple times", field_name)));
+            }
+            seen.push(field.number());
+        }
+
+        let has_colon = lexer.next_is(':')?;
+
+        if lexer.next_is('[')? {
+            if !is_repeated(field) {
+                return Err(lexer.error(format!("field '{}' is not repeated and cannot take a list", field_name)));
+            }
+
+            if !lexer.next_is(']')? {
+                loop {
+                    parse_field_value(lexer, field, has_colon, &mut buffer)?;
+                    if lexer.next_is(']')? {
+                        break;
+                    }
+                    lexer.expect(',')?;
+                }
+            }
+        } else {
+            parse_field_value(lexer, field, has_colon, &mut buffer)?;
+        }
+
+        if !lexer.next_is(',')? {
+            lexer.next_is(';')?;
+        }
+    }
+
+    Ok(buffer)
+}
+
+fn parse_field_value(lexer: &mut Lexer, field: &FieldDescriptorProto, has_colon: bool, buffer: &mut Vec<u8>) -> Result<(), TritonError> {
+    let number = field.number();
+
+    if field.r#type() == Type::Message {
+        let end = match lexer.next()? {
+            Some(Token::Punctuation('{')) => '}',
+            Some(Token::Punctuation('<')) => '>',
+            token => return Err(lexer.error(format!("expected '{{' to start message field '{}', found {:?}", field.name(), token))),
+        };
+
+        let content = parse_message(lexer, message_descriptor(field.type_name())?, Some(end))?;
+        put_length_delimited(buffer, number, &content);
+
+        return Ok(());
+    }
+
+    if !has_colon {
+        return Err(lexer.error(format!("expected ':' after field '{}'", field.name())));
+    }
+
+    let token = lexer.next()?.ok_or_else(|| lexer.error(format!("missing value of field '{}'", field.name())))?;
+    let invalid = |lexer: &Lexer, token: &Token| lexer.error(format!("invalid value {:?} for field '{}' of type {:?}", token, field.name(), field.r#type()));
+
+    match field.r#type() {
+        Type::String | Type::Bytes => {
+            let Token::String(value) = &token else { return Err(invalid(lexer, &token)) };
+            if field.r#type() == Type::String && std::str::from_utf8(value).is_err() {
+                return Err(lexer.error(format!("field '{}' is not valid UTF-8", field.name())));
+            }
+            put_length_delimited(buffer, number, value);
+        }
+        Type::Bool => {
+            let value = match &token {
+                Token::Identifier(value) if value == "true" || value == "True" || value == "t" => true,
+                Token::Identifier(value) if value == "false" || value == "False" || value == "f" => false,
+                Token::Number(value) if value == "1" => true,
+                Token::Number(value) if value == "0" => false,
+                _ => return Err(invalid(lexer, &token)),
+            };
+            put_key(buffer, number, WIRE_VARINT);
+            put_varint(buffer, value as u64);
+        }
+        Type::Enum => {
+            let value = match &token {
+                Token::Identifier(name) => {
+                    let enum_type = enum_descriptor(field.type_name())?;
+                    enum_type.value.iter().find(|value| value.name() == name).map(|value| value.number())
+                        .ok_or_else(|| lexer.error(format!("unknown value '{}' of enum {}", name, enum_type.name())))?
+                }
+                Token::Number(value) => parse_signed(value).and_then(|value| i32::try_from(value).ok()).ok_or_else(|| invalid(lexer, &token))?,
+                _ => return Err(invalid(lexer, &token)),
+            };
+            put_key(buffer, number, WIRE_VARINT);
+            put_varint(buffer, value as i64 as u64);
+        }
+        Type::Double | Type::Float => {
+            let value = match &token {
+                Token::Number(value) | Token::Identifier(value) => parse_float(value).ok_or_else(|| invalid(lexer, &token))?,
+                _ => return Err(invalid(lexer, &token)),
+            };
+            if field.r#type() == Type::Double {
+                put_key(buffer, number, WIRE_FIXED64);
+                buffer.extend_from_slice(&value.to_le_bytes());
+            } else {
+                put_key(buffer, number, WIRE_FIXED32);
+                buffer.extend_from_slice(&(value as f32).to_le_bytes());
+            }
+        }
+        integer_type => {
+            let Token::Number(value) = &token else { return Err(invalid(lexer, &token)) };
+            let out_of_range = || lexer.error(format!("value {} is out of range for field '{}'", value, field.name()));
+
+            match integer_type {
+                Type::Int64 | Type::Sfixed64 | Type::Sint64 => {
+                    let value = parse_signed(value).ok_or_else(out_of_range)?;
+                    put_integer(buffer, number, integer_type, value as u64);
+                }
+                Type::Int32 | Type::Sfixed32 | Type::Sint32 => {
+                    let value = parse_signed(value).and_then(|value| i32::try_from(value).ok()).ok_or_else(out_of_range)?;
+                    put_integer(buffer, number, integer_type, value as i64 as u64);
+                }
+                Type::Uint64 | Type::Fixed64 => {
+                    let value = parse_unsigned(value).ok_or_else(out_of_range)?;
+                    put_integer(buffer, number, integer_type, value);
+                }
+                Type::Uint32 | Type::Fixed32 => {
+                    let value = parse_unsigned(value).and_then(|value| u32::try_from(value).ok()).ok_or_else(out_of_range)?;
+                    put_integer(buffer, number, integer_type, value as u64);
+                }
+                _ => return Err(lexer.error(format!("unsupported type {:?} of field '{}'", integer_type, field.name()))),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn put_integer(buffer: &mut Vec<u8>, number: i32, integer_type: Type, value: u64) {
+    match integer_type {
+        Type::Sfixed64 | Type::Fixed64 => {
+            put_key(buffer, number, WIRE_FIXED64);
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        Type::Sfixed32 | Type::Fixed32 => {
+            put_key(buffer, number, WIRE_FIXED32);
+            buffer.extend_from_slice(&(value as u32).to_le_bytes());
+        }
+        Type::Sint64 => {
+            let value = value as i64;
+            put_key(buffer, number, WIRE_VARINT);
+            put_varint(buffer, ((value << 1) ^ (value >> 63)) as u64);
+        }
+        Type::Sint32 => {
+            let value = value as i64 as i32;
+            put_key(buffer, number, WIRE_VARINT);
+            put_varint(buffer, ((value << 1) ^ (value >> 31)) as u32 as u64);
+        }
+        _ => {
+            put_key(buffer, number, WIRE_VARINT);
+            put_varint(buffer, value);
+        }
+    }
+}
+
+fn parse_unsigned(value: &str) -> Option<u64> {
+    match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hexadecimal) => u64::from_str_radix(hexadecimal, 16).ok(),
+        None => value.strip_prefix('+').unwrap_or(value).parse().ok(),
+    }
+}
+
+fn parse_signed(value: &str) -> Option<i64> {
+    match value.strip_prefix('-') {
+        Some(magnitude) => {
+            let magnitude = parse_unsigned(magnitude)?;
+            if magnitude == 1 << 63 {
+                Some(i64::MIN)
+            } else {
+                i64::try_from(magnitude).ok().map(|magnitude| -magnitude)
+            }
+        }
+        None => parse_unsigned(value).and_then(|value| i64::try_from(value).ok()),
+    }
+}
+
+fn parse_float(value: &str) -> Option<f64> {
+    let (negative, magnitude) = match value.strip_prefix('-') {
+        Some(magnitude) => (true, magnitude),
+        None => (false, value),
+    };
+
+    let parsed = match magnitude.to_ascii_lowercase().as_str() {
+        "inf" | "infinity" => f64::INFINITY,
+        "nan" => f64::NAN,
+        lowercase => {
+            let number = lowercase.strip_suffix('f').filter(|number| !number.starts_with("0x")).unwrap_or(lowercase);
+            match number.parse::<f64>() {
+                Ok(parsed) => parsed,
+                Err(_) => parse_signed(number)? as f64,
+            }
+        }
+    };
+
+    Some(if negative { -parsed } else { parsed })
+}
+
+// ---------------------------------------------------------------------------
+// Binary decoder producing a tree of named fields for the printers
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+enum Value {
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    Enum(String),
+    Message(Vec<Field>),
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+enum FieldValue {
+    Single(Value),
+    Repeated(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+}
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+struct Field {
+    name: String,
+    value: FieldValue
+}
+
+enum WireValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Fixed32(u32),
+    LengthDelimited(&'a [u8]),
+}
+
+fn malformed(message: &str) -> TritonError {
+    TritonError::Parse(format!("malformed protobuf encoding: {}", message))
+}
+
+fn read_varint(bytes: &[u8], position: &mut usize) -> Result<u64, TritonError> {
+    let mut value = 0u64;
+    let mut shift = 0;
+
+    loop {
+        let byte = *bytes.get(*position).ok_or_else(|| malformed("truncated varint"))?;
+        *position += 1;
+        if shift >= 64 {
+            return Err(malformed("varint is too long"));
+        }
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+fn read_fixed<'a>(bytes: &'a [u8], position: &mut usize, length: usize) -> Result<&'a [u8], TritonError> {
+    let value = position.checked_add(length).and_then(|end| bytes.get(*position..end)).ok_or_else(|| malformed("truncated field"))?;
+    *position += length;
+
+    Ok(value)
+}
+
+fn read_wire_values(bytes: &[u8]) -> Result<Vec<(i32, WireValue<'_>)>, TritonError> {
+    let mut values = Vec::new();
+    let mut position = 0;
+
+    while position < bytes.len() {
+        let key = read_varint(bytes, &mut position)?;
+        let number = i32::try_from(key >> 3).map_err(|_| malformed("field number out of range"))?;
+
+        let value = match (key & 0x7) as u32 {
+            WIRE_VARINT => WireValue::Varint(read_varint(bytes, &mut position)?),
+            WIRE_FIXED64 => WireValue::Fixed64(u64::from_le_bytes(read_fixed(bytes, &mut position, 8)?.try_into().unwrap())),
+            WIRE_FIXED32 => WireValue::Fixed32(u32::from_le_bytes(read_fixed(bytes, &mut position, 4)?.try_into().unwrap())),
+            WIRE_LENGTH_DELIMITED => {
+                let length = usize::try_from(read_varint(bytes, &mut position)?).map_err(|_| malformed("length out of range"))?;
+                WireValue::LengthDelimited(read_fixed(bytes, &mut position, length)?)
+            }
+            wire_type => return Err(malformed(&format!("unsupported wire type {}", wire_type))),
+        };
+
+        values.push((number, value));
+    }
+
+    Ok(values)
+}
+
+fn scalar_wire_type(field_type: Type) -> u32 {
+    match field_type {
+        Type::Double | Type::Fixed64 | Type::Sfixed64 => WIRE_FIXED64,
+        Type::Float | Type::Fixed32 | Type::Sfixed32 => WIRE_FIXED32,
+        Type::String | Type::Bytes | Type::Message | Type::Group => WIRE_LENGTH_DELIMITED,
+        _ => WIRE_VARINT,
+    }
+}
+
+// Splits a packed repeated scalar field into its elements
+fn unpack(field_type: Type, bytes: &[u8]) -> Result<Vec<WireValue<'_>>, TritonError> {
+    let mut values = Vec::new();
+    let mut position = 0;
+
+    while position < bytes.len() {
+        values.push(match scalar_wire_type(field_type) {
+            WIRE_FIXED64 => WireValue::Fixed64(u64::from_le_bytes(read_fixed(bytes, &mut position, 8)?.try_into().unwrap())),
+            WIRE_FIXED32 => WireValue::Fixed32(u32::from_le_bytes(read_fixed(bytes, &mut position, 4)?.try_into().unwrap())),
+            _ => WireValue::Varint(read_varint(bytes, &mut position)?),
+        });
+    }
+
+    Ok(values)
+}
+
+fn decode_value(field: &FieldDescriptorProto, wire_value: &WireValue) -> Result<Value, TritonError> {
+    Ok(match (field.r#type(), wire_value) {
+        (Type::Bool, WireValue::Varint(value)) => Value::Bool(*value != 0),
+        (Type::Int64, WireValue::Varint(value)) => Value::Int(*value as i64),
+        (Type::Int32, WireValue::Varint(value)) => Value::Int(*value as i64 as i32 as i64),
+        (Type::Uint64, WireValue::Varint(value)) => Value::UInt(*value),
+        (Type::Uint32, WireValue::Varint(value)) => Value::UInt(*value as u32 as u64),
+        (Type::Sint64, WireValue::Varint(value)) => Value::Int(((*value >> 1) as i64) ^ -((*value & 1) as i64)),
+        (Type::Sint32, WireValue::Varint(value)) => Value::Int((((*value as u32) >> 1) as i32 ^ -((*value & 1) as i32)) as i64),
+        (Type::Enum, WireValue::Varint(value)) => {
+            let number = *value as i64 as i32;
+            match enum_descriptor(field.type_name())?.value.iter().find(|value| value.number() == number) {
+                Some(value) => Value::Enum(value.name().to_string()),
+                None => Value::Int(number as i64),
+            }
+        }
+        (Type::Double, WireValue::Fixed64(value)) => Value::Double(f64::from_bits(*value)),
+        (Type::Fixed64, WireValue::Fixed64(value)) => Value::UInt(*value),
+        (Type::Sfixed64, WireValue::Fixed64(value)) => Value::Int(*value as i64),
+        (Type::Float, WireValue::Fixed32(value)) => Value::Float(f32::from_bits(*value)),
+        (Type::Fixed32, WireValue::Fixed32(value)) => Value::UInt(*value as u64),
+        (Type::Sfixed32, WireValue::Fixed32(value)) => Value::Int(*value as i32 as i64),
+        (Type::String, WireValue::LengthDelimited(bytes)) => Value::String(String::from_utf8_lossy(bytes).to_string()),
+        (Type::Bytes, WireValue::LengthDelimited(bytes)) => Value::Bytes(bytes.to_vec()),
+        (Type::Message, WireValue::LengthDelimited(bytes)) => Value::Message(decode_message(message_descriptor(field.type_name())?, bytes)?),
+        (field_type, _) => return Err(malformed(&format!("unexpected wire type for field {} of type {:?}", field.name(), field_type))),
+    })
+}
+
+fn length_delimited<'a>(field: &FieldDescriptorProto, wire_value: &WireValue<'a>) -> Result<&'a [u8], TritonError> {
+    match wire_value {
+        WireValue::LengthDelimited(bytes) => Ok(bytes),
+        _ => Err(malformed(&format!("field {} is not length-delimited", field.name()))),
+    }
+}
+
+fn decode_message(message: &DescriptorProto, bytes: &[u8]) -> Result<Vec<Field>, TritonError> {
+    let wire_values = read_wire_values(bytes)?;
+    let mut fields = Vec::new();
+
+    for field in &message.field {
+        let occurrences: Vec<&WireValue> = wire_values.iter().filter(|(number, _)| *number == field.number()).map(|(_, value)| value).collect();
+        let Some(last) = occurrences.last() else { continue };
+
+        let value = if let Some(entry) = map_entry_descriptor(field)? {
+            let mut entries = Vec::new();
+            for occurrence in &occurrences {
+                let entry_values = read_wire_values(length_delimited(field, occurrence)?)?;
+                let decode_entry_field = |number: i32| {
+                    let entry_field = entry.field.iter().find(|field| field.number() == number)
+                        .ok_or_else(|| malformed(&format!("map entry {} has no field {}", entry.name(), number)))?;
+                    match entry_values.iter().rev().find(|(found, _)| *found == number) {
+                        Some((_, value)) => decode_value(entry_field, value),
+                        None => default_value(entry_field),
+                    }
+                };
+                entries.push((decode_entry_field(1)?, decode_entry_field(2)?));
+            }
+            entries.sort_by(|first, second| first.0.partial_cmp(&second.0).unwrap_or(std::cmp::Ordering::Equal));
+
+            FieldValue::Map(entries)
+        } else if is_repeated(field) {
+            let mut values = Vec::new();
+            for occurrence in occurrences {
+                match occurrence {
+                    WireValue::LengthDelimited(bytes) if scalar_wire_type(field.r#type()) != WIRE_LENGTH_DELIMITED => {
+                        for value in unpack(field.r#type(), bytes)? {
+                            values.push(decode_value(field, &value)?);
+                        }
+                    }
+                    value => values.push(decode_value(field, value)?),
+                }
+            }
+
+            FieldValue::Repeated(values)
+        } else if field.r#type() == Type::Message {
+            // Several occurrences of a message are merged, as concatenated encodings
+            let mut merged = Vec::new();
+            for occurrence in occurrences {
+                merged.extend_from_slice(length_delimited(field, occurrence)?);
+            }
+
+            FieldValue::Single(Value::Message(decode_message(message_descriptor(field.type_name())?, &merged)?))
+        } else {
+            FieldValue::Single(decode_value(field, last)?)
+        };
+
+        fields.push(Field { name: field.name().to_string(), value });
+    }
+
+    Ok(fields)
+}
+
+fn default_value(field: &FieldDescriptorProto) -> Result<Value, TritonError> {
+    Ok(match field.r#type() {
+        Type::Bool => Value::Bool(false),
+        Type::Int64 | Type::Int32 | Type::Sint64 | Type::Sint32 | Type::Sfixed64 | Type::Sfixed32 => Value::Int(0),
+        Type::Uint64 | Type::Uint32 | Type::Fixed64 | Type::Fixed32 => Value::UInt(0),
+        Type::Float => Value::Float(0.0),
+        Type::Double => Value::Double(0.0),
+        Type::String => Value::String(String::new()),
+        Type::Bytes => Value::Bytes(Vec::new()),
+        Type::Enum => decode_value(field, &WireValue::Varint(0))?,
+        _ => Value::Message(Vec::new()),
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Printers
+
+fn escape_text(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+
+    for &byte in bytes {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\'' => escaped.push_str("\\'"),
+            b'\\' => escaped.push_str("\\\\"),
+            b'\n' => escaped.push_str("\\n"),
+            b'\r' => escaped.push_str("\\r"),
+            b'\t' => escaped.push_str("\\t"),
+            0x20..=0x7e => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+
+    escaped
+}
+
+fn format_float(value: f64, display: String) -> String {
+    if value.is_nan() {
+        "nan".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "inf".to_string() } else { "-inf".to_string() }
+    } else {
+        display
+    }
+}
+
+fn text_scalar(value: &Value) -> Result<String, TritonError> {
+    Ok(match value {
+        Value::Bool(value) => value.to_string(),
+        Value::Int(value) => value.to_string(),
+        Value::UInt(value) => value.to_string(),
+        Value::Float(value) => format_float(*value as f64, value.to_string()),
+        Value::Double(value) => format_float(*value, value.to_string()),
+        Value::String(value) => format!("\"{}\"", escape_text(value.as_bytes())),
+        Value::Bytes(value) => format!("\"{}\"", escape_text(value)),
+        Value::Enum(value) => value.clone(),
+        Value::Message(_) => return Err(TritonError::Parse("a message is not a scalar value".to_string())),
+    })
+}
+
+fn write_text_field(name: &str, value: &Value, indent: usize, output: &mut String) -> Result<(), TritonError> {
+    let padding = "  ".repeat(indent);
+
+    match value {
+        Value::Message(fields) => {
+            output.push_str(&format!("{}{} {{\n", padding, name));
+            write_text(fields, indent + 1, output)?;
+            output.push_str(&format!("{}}}\n", padding));
+        }
+        scalar => output.push_str(&format!("{}{}: {}\n", padding, name, text_scalar(scalar)?)),
+    }
+
+    Ok(())
+}
+
+fn write_text(fields: &[Field], indent: usize, output: &mut String) -> Result<(), TritonError> {
+    let padding = "  ".repeat(indent);
+
+    for field in fields {
+        match &field.value {
+            FieldValue::Single(value) => write_text_field(&field.name, value, indent, output)?,
+            FieldValue::Repeated(values) if matches!(values.first(), Some(Value::Message(_))) => {
+                for value in values {
+                    write_text_field(&field.name, value, indent, output)?;
+                }
+            }
+            FieldValue::Repeated(values) => {
+                let values = values.iter().map(text_scalar).collect::<Result<Vec<String>, TritonError>>()?;
+                output.push_str(&format!("{}{}: [ {} ]\n", padding, field.name, values.join(", ")));
+            }
+            FieldValue::Map(entries) => {
+                for (key, value) in entries {
+                    output.push_str(&format!("{}{} {{\n", padding, field.name));
+                    write_text_field("key", key, indent + 1, output)?;
+                    write_text_field("value", value, indent + 1, output)?;
+                    output.push_str(&format!("{}}}\n", padding));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::new();
+
+    for character in value.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            character if (character as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", character as u32)),
+            character => escaped.push(character),
+        }
+    }
+
+    escaped
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+
+    for chunk in bytes.chunks(3) {
+        let group = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+fn json_float(value: f64, display: String) -> String {
+    if value.is_nan() {
+        "\"NaN\"".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "\"Infinity\"".to_string() } else { "\"-Infinity\"".to_string() }
+    } else {
+        display
+    }
+}
+
+fn write_json_value(value: &Value, indent: usize, output: &mut String) -> Result<(), TritonError> {
+    match value {
+        Value::Bool(value) => output.push_str(&value.to_string()),
+        Value::Int(value) => output.push_str(&value.to_string()),
+        Value::UInt(value) => output.push_str(&value.to_string()),
+        Value::Float(value) => output.push_str(&json_float(*value as f64, value.to_string())),
+        Value::Double(value) => output.push_str(&json_float(*value, value.to_string())),
+        Value::String(value) | Value::Enum(value) => output.push_str(&format!("\"{}\"", escape_json(value))),
+        Value::Bytes(value) => output.push_str(&format!("\"{}\"", base64(value))),
+        Value::Message(fields) => write_json(fields, indent, output)?,
+    }
+
+    Ok(())
+}
+
+fn json_key(value: &Value) -> Result<String, TritonError> {
+    match value {
+        Value::String(value) | Value::Enum(value) => Ok(value.clone()),
+        Value::Bool(value) => Ok(value.to_string()),
+        Value::Int(value) => Ok(value.to_string()),
+        Value::UInt(value) => Ok(value.to_string()),
+        value => Err(TritonError::Parse(format!("invalid map key {:?}", value))),
+    }
+}
+
+fn write_json(fields: &[Field], indent: usize, output: &mut String) -> Result<(), TritonError> {
+    let padding = "  ".repeat(indent + 1);
+
+    if fields.is_empty() {
+        output.push_str("{}");
+        return Ok(());
+    }
+
+    output.push_str("{\n");
+    for (index, field) in fields.iter().enumerate() {
+        output.push_str(&format!("{}\"{}\": ", padding, field.name));
+
+        match &field.value {
+            FieldValue::Single(value) => write_json_value(value, indent + 1, output)?,
+            FieldValue::Repeated(values) => {
+                output.push('[');
+                for (value_index, value) in values.iter().enumerate() {
+                    if value_index > 0 {
+                        output.push_str(", ");
+                    }
+                    write_json_value(value, indent + 1, output)?;
+                }
+                output.push(']');
+            }
+            FieldValue::Map(entries) => {
+                output.push_str("{\n");
+                for (entry_index, (key, value)) in entries.iter().enumerate() {
+                    output.push_str(&format!("{}  \"{}\": ", padding, escape_json(&json_key(key)?)));
+                    write_json_value(value, indent + 2, output)?;
+                    output.push_str(if entry_index + 1 < entries.len() { ",\n" } else { "\n" });
+                }
+                output.push_str(&format!("{}}}", padding));
+            }
+        }
+
+        output.push_str(if index + 1 < fields.len() { ",\n" } else { "\n" });
+    }
+    output.push_str(&format!("{}}}", "  ".repeat(indent)));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inference::{DataType, model_instance_group};
+
+    fn round_trip(text: &str) -> ModelConfig {
+        let config = parse_model_config(text).unwrap();
+        let emitted = model_config_to_pbtxt(&config).unwrap();
+        let reparsed = parse_model_config(&emitted).unwrap();
+
+        assert_eq!(config, reparsed, "emitted text:\n{}", emitted);
+        // Emission is deterministic
+        assert_eq!(emitted, model_config_to_pbtxt(&reparsed).unwrap());
+
+        config
+    }
+
+    #[test]
+    fn huggingface_example() {
+        let config = round_trip(include_str!("../examples/example-huggingface/config/config.pbtxt"));
+
+        assert_eq!(config.name, "distilbert-base-uncased");
+        assert_eq!(config.platform, "onnxruntime_onnx");
+        assert_eq!(config.max_batch_size, 16);
+        assert_eq!(config.input.len(), 2);
+        assert_eq!(config.input[1].name, "attention_mask");
+        assert_eq!(config.input[1].data_type(), DataType::TypeInt64);
+        assert_eq!(config.output[0].dims, [-1, 30522]);
+    }
+
+    #[test]
+    fn string_escapes() {
+        let config = round_trip(r#"name: "quote\" backslash\\ tab\t newline\n octal\101 hex\x42 single\'""#);
+        assert_eq!(config.name, "quote\" backslash\\ tab\t newline\n octalA hexB single'");
+
+        let config = round_trip("name: 'single \"quoted\"' platform: \"é\"");
+        assert_eq!(config.name, "single \"quoted\"");
+        assert_eq!(config.platform, "é");
+
+        // Adjacent strings are concatenated
+        assert_eq!(parse_model_config(r#"name: "a" "b""#).unwrap().name, "ab");
+    }
+
+    #[test]
+    fn numbers() {
+        let config = round_trip("max_batch_size: -3 input { name: \"x\" dims: [ -1, 0x10, +2 ] }");
+        assert_eq!(config.max_batch_size, -3);
+        assert_eq!(config.input[0].dims, [-1, 16, 2]);
+
+        let config = round_trip("input { name: \"x\" dims: -9223372036854775808 }");
+        assert_eq!(config.input[0].dims, [i64::MIN]);
+
+        assert!(parse_model_config("max_batch_size: 2147483648").is_err());
+        assert!(parse_model_config("max_batch_size: 1.5").is_err());
+    }
+
+    #[test]
+    fn special_floats() {
+        let text = "sequence_batching { control_input [ { name: \"START\" control [ { kind: CONTROL_SEQUENCE_START fp32_false_true: [ -inf, inf, nan, 1.5, -2 ] } ] } ] }";
+        let config = parse_model_config(text).unwrap();
+        let emitted = model_config_to_pbtxt(&config).unwrap();
+        assert!(emitted.contains("fp32_false_true: [ -inf, inf, nan, 1.5, -2 ]"), "{}", emitted);
+
+        // NaN is not equal to itself, compare the emitted text instead
+        assert_eq!(model_config_to_pbtxt(&parse_model_config(&emitted).unwrap()).unwrap(), emitted);
+
+        let json = model_config_to_json(&config).unwrap();
+        assert!(json.contains("[\"-Infinity\", \"Infinity\", \"NaN\", 1.5, -2]"), "{}", json);
+    }
+
+    #[test]
+    fn enums() {
+        let config = round_trip("input { name: \"x\" data_type: TYPE_FP32 } instance_group { kind: KIND_GPU gpus: [ 0, 1 ] }");
+        assert_eq!(config.input[0].data_type(), DataType::TypeFp32);
+        assert_eq!(config.instance_group[0].kind(), model_instance_group::Kind::Gpu);
+
+        // Enum values can be given by number
+        let config = parse_model_config("input { name: \"x\" data_type: 11 }").unwrap();
+        assert_eq!(config.input[0].data_type(), DataType::TypeFp32);
+
+        assert!(parse_model_config("input { data_type: TYPE_UNKNOWN_TO_TRITON }").is_err());
+    }
+
+    #[test]
+    fn map_fields() {
+        let text = r#"
+            parameters { key: "b" value { string_value: "2" } }
+            parameters [ { key: "a" value { string_value: "1" } } ]
+            cc_model_filenames { key: "sm_80" value: "model_80.plan" }
+            dynamic_batching {
+              priority_queue_policy { key: 10 value { max_queue_size: 4 } }
+              priority_queue_policy { key: 2 value { timeout_action: DELAY } }
+            }
+        "#;
+        let config = round_trip(text);
+        assert_eq!(config.parameters["a"].string_value, "1");
+        assert_eq!(config.parameters["b"].string_value, "2");
+        assert_eq!(config.cc_model_filenames["sm_80"], "model_80.plan");
+
+        // Entries are sorted by key, numerically for integer keys
+        let emitted = model_config_to_pbtxt(&config).unwrap();
+        assert!(emitted.find("key: \"a\"").unwrap() < emitted.find("key: \"b\"").unwrap());
+        assert!(emitted.find("key: 2").unwrap() < emitted.find("key: 10").unwrap());
+    }
+
+    #[test]
+    fn lists_of_messages() {
+        let text = r#"
+            # comment
+            input [
+              { name: "a", data_type: TYPE_INT32, dims: [ 1 ] },
+              < name: "b"; data_type: TYPE_INT32; dims: [ 2 ] >
+            ]
+            input { name: "c" data_type: TYPE_INT32 dims: [] }
+            instance_group [ { count: 2 }, { count: 1 kind: KIND_CPU } ]
+        "#;
+        let config = round_trip(text);
+        let names: Vec<&str> = config.input.iter().map(|input| input.name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert_eq!(config.input[1].dims, [2]);
+        assert!(config.input[2].dims.is_empty());
+        assert_eq!(config.instance_group.len(), 2);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(parse_model_config("unknown_field: 1"), Err(TritonError::Parse(_))));
+        assert!(matches!(parse_model_config("name: \"a\" name: \"b\""), Err(TritonError::Parse(_))));
+        assert!(matches!(parse_model_config("input { name: \"a\""), Err(TritonError::Parse(_))));
+        assert!(matches!(parse_model_config("name: \"unterminated"), Err(TritonError::Parse(_))));
+        assert!(matches!(parse_model_config("max_batch_size: [ 1 ]"), Err(TritonError::Parse(_))));
+
+        match parse_model_config("name: \"a\"\n\nbogus: 1") {
+            Err(TritonError::Parse(message)) => assert!(message.starts_with("line 3"), "{}", message),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn json_output() {
+        let config = parse_model_config(r#"
+            name: "model\"1"
+            max_batch_size: 8
+            input { name: "x" data_type: TYPE_FP32 dims: [ -1, 3 ] }
+            parameters { key: "k" value { string_value: "v" } }
+        "#).unwrap();
+
+        let expected = r#"{
+  "name": "model\"1",
+  "max_batch_size": 8,
+  "input": [{
+    "name": "x",
+    "data_type": "TYPE_FP32",
+    "dims": [-1, 3]
+  }],
+  "parameters": {
+    "k": {
+      "string_value": "v"
+    }
+  }
+}"#;
+        assert_eq!(model_config_to_json(&config).unwrap(), expected);
+        assert_eq!(model_config_to_json(&ModelConfig::default()).unwrap(), "{}");
+    }
+
+    #[test]
+    fn base64_encoding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(&[0xfb, 0xff]), "+/8=");
+    }
+
+    #[test]
+    fn malformed_encoding() {
+        let message = message_descriptor(MODEL_CONFIG_MESSAGE).unwrap();
+
+        // Truncated length-delimited field
+        assert!(matches!(decode_message(message, &[0x0a, 0x05, b'a']), Err(TritonError::Parse(_))));
+        // Truncated varint
+        assert!(matches!(decode_message(message, &[0x20, 0x80]), Err(TritonError::Parse(_))));
+        // Varint for a string field
+        assert!(matches!(decode_message(message, &[0x08, 0x01]), Err(TritonError::Parse(_))));
+        // Unsupported wire type
+        assert!(matches!(decode_message(message, &[0x0b]), Err(TritonError::Parse(_))));
+
+        assert!(matches!(message_descriptor(".inference.Unknown"), Err(TritonError::Parse(_))));
+    }
+}
@@ -0,0 +1,201 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::runtime::{Builder, Handle, Runtime};
+
+use crate::async_client::AsyncTritonClient;
+use crate::error::TritonError;
+
+// Time given to the server to unregister a region when its handle is dropped
+const UNREGISTER_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy)]
+pub(crate) enum SharedMemoryKind {
+    System,
+    #[cfg(feature = "cuda")]
+    Cuda,
+}
+
+/// Registration of a shared memory region on the server, owned by its region handle
+/// and removed when the handle is dropped.
+pub(crate) struct Registration {
+    client: AsyncTritonClient,
+    kind: SharedMemoryKind,
+    // Set for the blocking client, so that its runtime outlives the region
+    runtime: Option<Arc<Runtime>>,
+    timeout: Duration
+}
+
+impl Registration {
+    pub(crate) fn new(client: AsyncTritonClient, kind: SharedMemoryKind) -> Self {
+        Registration { client, kind, runtime: None, timeout: UNREGISTER_TIMEOUT }
+    }
+
+    pub(crate) fn keep_runtime(&mut self, runtime: Arc<Runtime>) {
+        self.runtime = Some(runtime);
+    }
+
+    /// Unregisters the region and waits for the answer of the server.
+    pub(crate) async fn unregister(self, name: &str) -> Result<(), TritonError> {
+        let Registration { client, kind, runtime, .. } = self;
+
+        let result = match kind {
+            SharedMemoryKind::System => client.unregister_system_shared_memory(name).await.map(|_| ()),
+            #[cfg(feature = "cuda")]
+            SharedMemoryKind::Cuda => client.unregister_cuda_shared_memory(name).await.map(|_| ()),
+        };
+
+        release(runtime);
+        result
+    }
+
+    /// Unregisters the region, ignoring errors, and never panics (it is called on drop).
+    ///
+    /// Inside an async context the request is sent in the background as blocking is not
+    /// allowed, the server may still hold the registration when this returns. Otherwise it
+    /// is awaited on the runtime of the blocking client or, as the runtime of an async
+    /// client may have been shut down, on a temporary runtime. The server is not waited for
+    /// more than `UNREGISTER_TIMEOUT`.
+    pub(crate) fn unregister_best_effort(mut self, name: String) {
+        let runtime = self.runtime.take();
+
+        if let Ok(current) = Handle::try_current() {
+            current.spawn(async move { let _ = self.unregister(&name).await; });
+            release(runtime);
+            return;
+        }
+
+        let runtime = match runtime {
+            Some(runtime) => runtime,
+            None => match Builder::new_current_thread().enable_all().build() {
+                Ok(runtime) => Arc::new(runtime),
+                Err(_) => return,
+            },
+        };
+
+        let timeout = self.timeout;
+        let _ = runtime.block_on(async move { tokio::time::timeout(timeout, self.unregister(&name)).await });
+        release(Some(runtime));
+    }
+}
+
+// Dropping the last reference to a runtime blocks, which panics inside an async context
+fn release(runtime: Option<Arc<Runtime>>) {
+    if let Some(runtime) = runtime.and_then(|runtime| Arc::try_unwrap(runtime).ok()) {
+        if Handle::try_current().is_ok() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::process;
+    use std::time::Instant;
+
+    use crate::builder::ClientBuilder;
+    use crate::system_shared_memory::SystemSharedMemoryRegionHandle;
+
+    // Nothing listens on port 1, requests fail without leaving the host
+    fn client() -> AsyncTritonClient {
+        ClientBuilder::new("http://127.0.0.1:1").connect_lazy().unwrap()
+    }
+
+    #[test]
+    fn client_runtime_shut_down() {
+        let runtime = Builder::new_multi_thread().enable_all().build().unwrap();
+        let registration = {
+            let _guard = runtime.enter();
+            Registration::new(client(), SharedMemoryKind::System)
+        };
+        drop(runtime);
+
+        registration.unregister_best_effort("region".to_string());
+    }
+
+    #[test]
+    fn blocking_client_runtime() {
+        let runtime = Arc::new(Builder::new_multi_thread().enable_all().build().unwrap());
+        let mut registration = {
+            let _guard = runtime.enter();
+            Registration::new(client(), SharedMemoryKind::System)
+        };
+        registration.keep_runtime(runtime.clone());
+
+        registration.unregister_best_effort("region".to_string());
+        assert_eq!(Arc::strong_count(&runtime), 1);
+    }
+
+    #[tokio::test]
+    async fn inside_async_context() {
+        Registration::new(client(), SharedMemoryKind::System).unregister_best_effort("region".to_string());
+
+        // The last reference to the runtime of a blocking client is released in the background
+        let runtime = Builder::new_current_thread().enable_all().build().unwrap();
+        let mut registration = Registration::new(client(), SharedMemoryKind::System);
+        registration.keep_runtime(Arc::new(runtime));
+        registration.unregister_best_effort("region".to_string());
+
+        assert!(Registration::new(client(), SharedMemoryKind::System).unregister("region").await.is_err());
+    }
+
+    // The listener accepts connections in its backlog but never answers
+    #[test]
+    fn unresponsive_server() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let runtime = Arc::new(Builder::new_multi_thread().enable_all().build().unwrap());
+
+        for keep_runtime in [false, true] {
+            let mut registration = {
+                let _guard = runtime.enter();
+                Registration::new(ClientBuilder::new(address.as_str()).connect_lazy().unwrap(), SharedMemoryKind::System)
+            };
+            registration.timeout = Duration::from_millis(200);
+            if keep_runtime {
+                registration.keep_runtime(runtime.clone());
+            }
+
+            let mut handle = SystemSharedMemoryRegionHandle::create("unresponsive", &format!("/triton_rust_test_unresponsive_{}", process::id()), 64).unwrap();
+            handle.set_registration(registration);
+
+            let start = Instant::now();
+            drop(handle);
+            assert!(start.elapsed() < Duration::from_secs(5));
+        }
+    }
+}
@@ -0,0 +1,125 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use crate::error::TritonError;
+use crate::inference::{ModelConfig, ModelRepositoryParameter, model_repository_parameter};
+use crate::model_config::{model_config_to_json, read_model_config};
+
+/// Overrides sent with a model load request.
+///
+/// `config` replaces the model configuration (as JSON) and `file` provides the model
+/// files from memory, keyed by their path in the model directory (e.g. `1/model.onnx`).
+/// The server requires a configuration whenever files are provided.
+#[derive(Debug, Clone, Default)]
+pub struct ModelLoadOptions {
+    config: Option<String>,
+    files: BTreeMap<String, Vec<u8>>
+}
+
+impl ModelLoadOptions {
+    pub fn new() -> Self {
+        ModelLoadOptions::default()
+    }
+
+    /// Model configuration in JSON format, overriding the one in the repository.
+    pub fn config(mut self, config_json: &str) -> Self {
+        self.config = Some(config_json.to_string());
+        self
+    }
+
+    /// Model configuration overriding the one in the repository.
+    pub fn model_config(mut self, config: &ModelConfig) -> Result<Self, TritonError> {
+        self.config = Some(model_config_to_json(config)?);
+        Ok(self)
+    }
+
+    pub fn file(mut self, path: &str, content: Vec<u8>) -> Self {
+        self.files.insert(path.to_string(), content);
+        self
+    }
+
+    /// Packages the files of a local model directory (version subdirectories and
+    /// label files). A `config.pbtxt` at the root of the directory is converted to the
+    /// JSON configuration, otherwise it has to be given with `config`.
+    pub fn from_model_directory<P: AsRef<Path>>(model_directory: P) -> Result<Self, TritonError> {
+        let mut options = ModelLoadOptions::new();
+        add_directory_files(model_directory.as_ref(), "", &mut options.files)?;
+
+        let config_path = model_directory.as_ref().join("config.pbtxt");
+        if config_path.is_file() {
+            options = options.model_config(&read_model_config(config_path)?)?;
+        }
+
+        Ok(options)
+    }
+
+    pub fn file_paths(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(|path| path.as_str())
+    }
+
+    pub(crate) fn into_parameters(self) -> Result<HashMap<String, ModelRepositoryParameter>, TritonError> {
+        let mut parameters = HashMap::new();
+
+        if !self.files.is_empty() && self.config.is_none() {
+            return Err(TritonError::InvalidArgument("a model configuration is required to load a model from files".to_string()));
+        }
+
+        if let Some(config) = self.config {
+            parameters.insert("config".to_string(), ModelRepositoryParameter { parameter_choice: Some(model_repository_parameter::ParameterChoice::StringParam(config)) });
+        }
+
+        for (path, content) in self.files {
+            parameters.insert(format!("file:{}", path), ModelRepositoryParameter { parameter_choice: Some(model_repository_parameter::ParameterChoice::BytesParam(content)) });
+        }
+
+        Ok(parameters)
+    }
+}
+
+fn add_directory_files(directory: &Path, prefix: &str, files: &mut BTreeMap<String, Vec<u8>>) -> Result<(), TritonError> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let relative_path = if prefix.is_empty() { file_name.clone() } else { format!("{}/{}", prefix, file_name) };
+
+        if entry.file_type()?.is_dir() {
+            add_directory_files(&entry.path(), &relative_path, files)?;
+        } else if relative_path != "config.pbtxt" {
+            files.insert(relative_path, fs::read(entry.path())?);
+        }
+    }
+
+    Ok(())
+}
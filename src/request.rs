@@ -0,0 +1,435 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::time::Duration;
+
+use ndarray::{ArrayBase, ArrayViewMut, Data, Dimension, IxDyn};
+
+use crate::error::TritonError;
+use crate::inference::{InferParameter, ModelInferRequest, infer_parameter, log_settings_request};
+use crate::inference::model_infer_request::{InferInputTensor, InferRequestedOutputTensor};
+use crate::shared_memory_pool::SystemSharedMemorySlice;
+use crate::tensor::{DataType, FixedSizeElement, Tensor, TensorElement, checked_size, shape_to_usize};
+
+impl From<bool> for InferParameter {
+    fn from(value: bool) -> Self {
+        InferParameter { parameter_choice: Some(infer_parameter::ParameterChoice::BoolParam(value)) }
+    }
+}
+
+impl From<i64> for InferParameter {
+    fn from(value: i64) -> Self {
+        InferParameter { parameter_choice: Some(infer_parameter::ParameterChoice::Int64Param(value)) }
+    }
+}
+
+impl From<&str> for InferParameter {
+    fn from(value: &str) -> Self {
+        InferParameter { parameter_choice: Some(infer_parameter::ParameterChoice::StringParam(value.to_string())) }
+    }
+}
+
+impl From<String> for InferParameter {
+    fn from(value: String) -> Self {
+        InferParameter { parameter_choice: Some(infer_parameter::ParameterChoice::StringParam(value)) }
+    }
+}
+
+impl From<bool> for log_settings_request::SettingValue {
+    fn from(value: bool) -> Self {
+        log_settings_request::SettingValue { parameter_choice: Some(log_settings_request::setting_value::ParameterChoice::BoolParam(value)) }
+    }
+}
+
+impl From<u32> for log_settings_request::SettingValue {
+    fn from(value: u32) -> Self {
+        log_settings_request::SettingValue { parameter_choice: Some(log_settings_request::setting_value::ParameterChoice::Uint32Param(value)) }
+    }
+}
+
+impl From<&str> for log_settings_request::SettingValue {
+    fn from(value: &str) -> Self {
+        log_settings_request::SettingValue { parameter_choice: Some(log_settings_request::setting_value::ParameterChoice::StringParam(value.to_string())) }
+    }
+}
+
+pub(crate) fn shared_memory_parameters(region: &str, offset: u64, byte_size: u64) -> Result<HashMap<String, InferParameter>, TritonError> {
+    let byte_size = i64::try_from(byte_size).map_err(|_| TritonError::InvalidArgument(format!("shared memory size {} does not fit in an int64 parameter", byte_size)))?;
+    let offset = i64::try_from(offset).map_err(|_| TritonError::InvalidArgument(format!("shared memory offset {} does not fit in an int64 parameter", offset)))?;
+
+    Ok(HashMap::from([
+        ("shared_memory_region".to_string(), InferParameter::from(region)),
+        ("shared_memory_byte_size".to_string(), InferParameter::from(byte_size)),
+        ("shared_memory_offset".to_string(), InferParameter::from(offset))
+    ]))
+}
+
+#[derive(Debug, Clone)]
+struct SharedMemoryLocation {
+    region: String,
+    offset: u64,
+    byte_size: u64
+}
+
+#[derive(Debug, Clone)]
+enum InputContent {
+    Raw(Vec<u8>),
+    SharedMemory(SharedMemoryLocation),
+    // The array could not be serialized, reported by `build`
+    Invalid(String),
+}
+
+#[derive(Debug, Clone)]
+struct RequestInput {
+    name: String,
+    datatype: DataType,
+    shape: Vec<i64>,
+    content: InputContent
+}
+
+#[derive(Debug, Clone)]
+struct RequestOutput {
+    name: String,
+    shared_memory: Option<SharedMemoryLocation>
+}
+
+/// Builder of an inference request.
+///
+/// Each input is given together with its content, so descriptions and contents
+/// cannot be misaligned. The request is checked by `build` before being sent.
+#[derive(Debug, Clone)]
+pub struct InferRequest {
+    model_name: String,
+    model_version: String,
+    id: String,
+    parameters: HashMap<String, InferParameter>,
+    inputs: Vec<RequestInput>,
+    outputs: Vec<RequestOutput>
+}
+
+impl InferRequest {
+    pub fn new(model_name: &str) -> Self {
+        InferRequest {
+            model_name: model_name.to_string(),
+            model_version: String::new(),
+            id: String::new(),
+            parameters: HashMap::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new()
+        }
+    }
+
+    pub fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    pub fn model_version(&self) -> &str {
+        &self.model_version
+    }
+
+    /// Model version to use, the server chooses one if unset.
+    pub fn version(mut self, model_version: &str) -> Self {
+        self.model_version = model_version.to_string();
+        self
+    }
+
+    pub fn id(mut self, request_id: &str) -> Self {
+        self.id = request_id.to_string();
+        self
+    }
+
+    /// Adds an input with the shape and datatype of an ndarray.
+    pub fn input<T: TensorElement, S: Data<Elem = T>, D: Dimension>(mut self, name: &str, array: &ArrayBase<S, D>) -> Self {
+        match Tensor::from_array(name, array) {
+            Ok(tensor) => self.input_tensor(tensor),
+            Err(err) => {
+                self.inputs.push(RequestInput {
+                    name: name.to_string(),
+                    datatype: T::DATATYPE,
+                    shape: array.shape().iter().map(|dim| *dim as i64).collect(),
+                    content: InputContent::Invalid(err.to_string())
+                });
+                self
+            }
+        }
+    }
+
+    pub fn input_tensor(mut self, tensor: Tensor) -> Self {
+        let name = tensor.name().to_string();
+        let datatype = tensor.datatype();
+        let shape = tensor.shape().to_vec();
+
+        self.inputs.push(RequestInput {
+            name,
+            datatype,
+            shape,
+            content: InputContent::Raw(tensor.into_data())
+        });
+        self
+    }
+
+    /// Adds an input whose content has already been written to a registered shared memory region.
+    pub fn shm_input(mut self, name: &str, datatype: DataType, shape: &[i64], region: &str, offset: u64, byte_size: u64) -> Self {
+        self.inputs.push(RequestInput {
+            name: name.to_string(),
+            datatype,
+            shape: shape.to_vec(),
+            content: InputContent::SharedMemory(SharedMemoryLocation { region: region.to_string(), offset, byte_size })
+        });
+        self
+    }
+
+    /// Adds an input whose content has already been written to a slice of a shared memory pool.
+    pub fn shm_input_slice(self, name: &str, datatype: DataType, shape: &[i64], slice: &SystemSharedMemorySlice) -> Self {
+        self.shm_input(name, datatype, shape, slice.region_name(), slice.offset() as u64, slice.byte_size() as u64)
+    }
+
+    /// Adds an input produced in place at the start of a pool slice: `fill` receives a view of
+    /// `shape` elements to write the tensor into (e.g. with `assign` or `Zip`). The datatype
+    /// and the shared memory parameters are derived from `T` and the slice.
+    pub fn shm_input_with<T, F>(self, name: &str, shape: &[usize], slice: &mut SystemSharedMemorySlice, fill: F) -> Result<Self, TritonError>
+    where
+        T: FixedSizeElement,
+        F: FnOnce(ArrayViewMut<'_, T, IxDyn>)
+    {
+        let too_large = || TritonError::Shape(format!("input '{}' of shape {:?} is too large", name, shape));
+        let byte_size = checked_size(shape, mem::size_of::<T>()).ok_or_else(too_large)?;
+        let dims = shape.iter().map(|dim| i64::try_from(*dim)).collect::<Result<Vec<i64>, _>>().map_err(|_| too_large())?;
+
+        fill(slice.view_mut::<T>(0, shape)?);
+
+        Ok(self.shm_input(name, T::DATATYPE, &dims, slice.region_name(), slice.offset() as u64, byte_size as u64))
+    }
+
+    /// Requests an output, returned in the response.
+    pub fn output(mut self, name: &str) -> Self {
+        self.outputs.push(RequestOutput { name: name.to_string(), shared_memory: None });
+        self
+    }
+
+    /// Requests an output to be written to a registered shared memory region.
+    pub fn shm_output(mut self, name: &str, region: &str, offset: u64, byte_size: u64) -> Self {
+        self.outputs.push(RequestOutput {
+            name: name.to_string(),
+            shared_memory: Some(SharedMemoryLocation { region: region.to_string(), offset, byte_size })
+        });
+        self
+    }
+
+    /// Requests an output to be written to a slice of a shared memory pool.
+    pub fn shm_output_slice(self, name: &str, slice: &SystemSharedMemorySlice) -> Self {
+        self.shm_output(name, slice.region_name(), slice.offset() as u64, slice.byte_size() as u64)
+    }
+
+    pub fn parameter<P: Into<InferParameter>>(mut self, key: &str, value: P) -> Self {
+        self.parameters.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Priority of the request for models using priority levels, 1 being the highest.
+    pub fn priority(self, priority: u32) -> Self {
+        self.parameter("priority", i64::from(priority))
+    }
+
+    /// Timeout of the request in the server queue, with microsecond precision.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.parameter("timeout", i64::try_from(timeout.as_micros()).unwrap_or(i64::MAX))
+    }
+
+    pub fn input_names(&self) -> impl Iterator<Item = &str> {
+        self.inputs.iter().map(|input| input.name.as_str())
+    }
+
+    pub fn output_names(&self) -> impl Iterator<Item = &str> {
+        self.outputs.iter().map(|output| output.name.as_str())
+    }
+
+    /// Returns the datatype and shape of an input.
+    pub fn input_spec(&self, name: &str) -> Option<(DataType, &[i64])> {
+        self.inputs.iter().find(|input| input.name == name).map(|input| (input.datatype, input.shape.as_slice()))
+    }
+
+    /// Name, datatype and shape of every input, in the order they were added.
+    pub fn input_specs(&self) -> impl Iterator<Item = (&str, DataType, &[i64])> {
+        self.inputs.iter().map(|input| (input.name.as_str(), input.datatype, input.shape.as_slice()))
+    }
+
+    fn validate(&self) -> Result<(), TritonError> {
+        if self.model_name.is_empty() {
+            return Err(TritonError::InvalidArgument("the model name is empty".to_string()));
+        }
+
+        let mut input_names = HashSet::new();
+        for input in &self.inputs {
+            if !input_names.insert(input.name.as_str()) {
+                return Err(TritonError::InvalidArgument(format!("input '{}' is given more than once", input.name)));
+            }
+
+            // Raw contents come from a Tensor, already consistent with its shape
+            if let InputContent::SharedMemory(location) = &input.content {
+                check_shared_memory_location(&input.name, location)?;
+
+                let dims = shape_to_usize(&input.shape)?;
+                if let Some(element_size) = input.datatype.size() {
+                    let byte_size = checked_size(&dims, element_size)
+                        .ok_or_else(|| TritonError::Shape(format!("input '{}' of shape {:?} is too large", input.name, input.shape)))?;
+                    if byte_size as u64 != location.byte_size {
+                        return Err(TritonError::Shape(format!("input '{}' of shape {:?} and datatype {} requires {} bytes, shared memory size is {}", input.name, input.shape, input.datatype, byte_size, location.byte_size)));
+                    }
+                }
+            }
+        }
+
+        let mut output_names = HashSet::new();
+        for output in &self.outputs {
+            if !output_names.insert(output.name.as_str()) {
+                return Err(TritonError::InvalidArgument(format!("output '{}' is requested more than once", output.name)));
+            }
+
+            if let Some(location) = &output.shared_memory {
+                check_shared_memory_location(&output.name, location)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the request and assembles the `ModelInferRequest` to send.
+    pub fn build(self) -> Result<ModelInferRequest, TritonError> {
+        self.validate()?;
+
+        // raw_input_contents holds the contents of the inputs which are not in shared
+        // memory, in the order of these inputs
+        let mut inputs = Vec::with_capacity(self.inputs.len());
+        let mut raw_input_contents = Vec::new();
+
+        for input in self.inputs {
+            let parameters = match input.content {
+                InputContent::Raw(content) => {
+                    raw_input_contents.push(content);
+                    HashMap::new()
+                }
+                InputContent::SharedMemory(location) => shared_memory_parameters(&location.region, location.offset, location.byte_size)?,
+                InputContent::Invalid(message) => return Err(TritonError::DataType(format!("input '{}': {}", input.name, message))),
+            };
+
+            inputs.push(InferInputTensor {
+                name: input.name,
+                datatype: input.datatype.as_str().to_string(),
+                shape: input.shape,
+                parameters,
+                contents: None
+            });
+        }
+
+        let mut outputs = Vec::with_capacity(self.outputs.len());
+        for output in self.outputs {
+            let parameters = match output.shared_memory {
+                Some(location) => shared_memory_parameters(&location.region, location.offset, location.byte_size)?,
+                None => HashMap::new(),
+            };
+
+            outputs.push(InferRequestedOutputTensor {
+                name: output.name,
+                parameters
+            });
+        }
+
+        Ok(ModelInferRequest {
+            model_name: self.model_name,
+            model_version: self.model_version,
+            id: self.id,
+            parameters: self.parameters,
+            inputs,
+            outputs,
+            raw_input_contents
+        })
+    }
+}
+
+fn check_shared_memory_location(name: &str, location: &SharedMemoryLocation) -> Result<(), TritonError> {
+    if location.region.is_empty() {
+        return Err(TritonError::InvalidArgument(format!("tensor '{}' has an empty shared memory region name", name)));
+    }
+
+    if location.offset.checked_add(location.byte_size).is_none() {
+        return Err(TritonError::InvalidArgument(format!("tensor '{}' has an invalid shared memory offset", name)));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn mixed_raw_and_shared_memory_inputs() {
+        let request = InferRequest::new("model")
+            .input("a", &array![1i32, 2])
+            .shm_input("b", DataType::Fp32, &[4], "region", 64, 16)
+            .input("c", &array![3i32])
+            .build()
+            .unwrap();
+
+        let names: Vec<&str> = request.inputs.iter().map(|input| input.name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert!(request.inputs[0].parameters.is_empty());
+        assert!(request.inputs[1].parameters.contains_key("shared_memory_region"));
+
+        assert_eq!(request.raw_input_contents.len(), 2);
+        assert_eq!(request.raw_input_contents[0], [1, 0, 0, 0, 2, 0, 0, 0]);
+        assert_eq!(request.raw_input_contents[1], [3, 0, 0, 0]);
+    }
+
+    #[test]
+    fn shared_memory_inputs_only() {
+        let request = InferRequest::new("model")
+            .shm_input("b", DataType::Fp32, &[4], "region", 0, 16)
+            .build()
+            .unwrap();
+
+        assert!(request.raw_input_contents.is_empty());
+    }
+
+    #[test]
+    fn shared_memory_size_overflow() {
+        // 2^62 * 4 elements of 4 bytes wrap around to 0 bytes
+        let result = InferRequest::new("model").shm_input("b", DataType::Fp32, &[1 << 62, 4], "region", 0, 0).build();
+        assert!(matches!(result, Err(TritonError::Shape(_))));
+
+        let result = InferRequest::new("model").shm_input("b", DataType::Fp32, &[4], "region", 0, 15).build();
+        assert!(matches!(result, Err(TritonError::Shape(message)) if message.contains("requires 16 bytes")));
+    }
+}
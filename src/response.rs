@@ -0,0 +1,161 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+use std::ops::Deref;
+
+use ndarray::ArrayD;
+
+use crate::error::TritonError;
+use crate::inference::ModelInferResponse;
+use crate::tensor::{Tensor, TensorElement};
+
+/// Inference response giving access to the outputs by name.
+#[derive(Debug, Clone, Default)]
+pub struct InferResponse {
+    response: ModelInferResponse
+}
+
+impl InferResponse {
+    pub fn new(response: ModelInferResponse) -> Self {
+        InferResponse { response }
+    }
+
+    pub fn output_names(&self) -> impl Iterator<Item = &str> {
+        self.response.outputs.iter().map(|output| output.name.as_str())
+    }
+
+    /// Returns an output as an owned tensor, using the shape and datatype sent by the server.
+    ///
+    /// `raw_output_contents[i]` holds the content of `outputs[i]`, outputs written to
+    /// shared memory have no content in the response and must be read from their region.
+    pub fn output_tensor(&self, name: &str) -> Result<Tensor, TritonError> {
+        let index = self.response.outputs.iter().position(|output| output.name == name)
+            .ok_or_else(|| TritonError::InvalidArgument(format!("response has no output named '{}'", name)))?;
+        let output = &self.response.outputs[index];
+
+        if output.parameters.contains_key("shared_memory_region") {
+            return Err(TritonError::InvalidArgument(format!("output '{}' was written to shared memory", name)));
+        }
+
+        let content = self.response.raw_output_contents.get(index)
+            .ok_or_else(|| TritonError::InvalidArgument(format!("response has no raw content for output '{}'", name)))?;
+
+        Tensor::new(&output.name, output.datatype.parse()?, output.shape.clone(), content.clone())
+    }
+
+    pub fn output<T: TensorElement>(&self, name: &str) -> Result<ArrayD<T>, TritonError> {
+        self.output_tensor(name)?.to_array::<T>()
+    }
+
+    pub fn into_inner(self) -> ModelInferResponse {
+        self.response
+    }
+}
+
+impl From<ModelInferResponse> for InferResponse {
+    fn from(response: ModelInferResponse) -> Self {
+        InferResponse::new(response)
+    }
+}
+
+impl Deref for InferResponse {
+    type Target = ModelInferResponse;
+
+    fn deref(&self) -> &ModelInferResponse {
+        &self.response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::inference::model_infer_response::InferOutputTensor;
+    use crate::request::shared_memory_parameters;
+
+    fn output(name: &str, datatype: &str, shape: &[i64]) -> InferOutputTensor {
+        InferOutputTensor { name: name.to_string(), datatype: datatype.to_string(), shape: shape.to_vec(), ..Default::default() }
+    }
+
+    fn response() -> InferResponse {
+        let mut labels = Vec::new();
+        String::encode(["cat".to_string(), "dog".to_string()].iter(), &mut labels).unwrap();
+
+        let mut shm = output("shm", "FP32", &[4]);
+        shm.parameters = shared_memory_parameters("region", 0, 16).unwrap();
+
+        InferResponse::new(ModelInferResponse {
+            outputs: vec![output("logits", "FP32", &[2, 2]), output("labels", "BYTES", &[2]), output("short", "INT64", &[2]), shm],
+            raw_output_contents: vec![
+                [1.0f32, 2.0, 3.0, 4.0].iter().flat_map(|value| value.to_le_bytes()).collect(),
+                labels,
+                vec![0; 12],
+            ],
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn outputs_by_name() {
+        let response = response();
+        assert_eq!(response.output_names().collect::<Vec<_>>(), ["logits", "labels", "short", "shm"]);
+
+        assert_eq!(response.output::<f32>("logits").unwrap(), ndarray::arr2(&[[1.0f32, 2.0], [3.0, 4.0]]).into_dyn());
+        assert_eq!(response.output::<String>("labels").unwrap(), ndarray::arr1(&["cat".to_string(), "dog".to_string()]).into_dyn());
+
+        let tensor = response.output_tensor("logits").unwrap();
+        assert_eq!((tensor.name(), tensor.shape()), ("logits", &[2i64, 2][..]));
+        assert!(matches!(response.output::<i32>("logits"), Err(TritonError::DataType(_))));
+    }
+
+    #[test]
+    fn byte_length_mismatch() {
+        // 12 bytes for 2 INT64 elements
+        assert!(matches!(response().output_tensor("short"), Err(TritonError::Shape(_))));
+    }
+
+    #[test]
+    fn shared_memory_output() {
+        let err = response().output_tensor("shm").unwrap_err();
+        assert!(matches!(&err, TritonError::InvalidArgument(message) if message.contains("shared memory")), "{}", err);
+    }
+
+    #[test]
+    fn missing_output() {
+        let err = response().output::<f32>("probabilities").unwrap_err();
+        assert!(matches!(&err, TritonError::InvalidArgument(message) if message.contains("no output named 'probabilities'")), "{}", err);
+
+        // Output without raw content
+        let mut response = response().into_inner();
+        response.raw_output_contents.truncate(1);
+        assert!(matches!(InferResponse::new(response).output_tensor("labels"), Err(TritonError::InvalidArgument(_))));
+    }
+}
@@ -0,0 +1,315 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use tonic::Code;
+
+use crate::error::TritonError;
+
+// Upper bound of `max_backoff`, so that the jittered delay stays representable
+const BACKOFF_LIMIT: Duration = Duration::from_secs(3600);
+
+/// Retry policy for transient failures, e.g. while the server restarts or a model
+/// is reloading.
+///
+/// Failed calls are retried with an exponential backoff while the error is retryable
+/// and `max_attempts` is not reached. Inference requests may have been executed
+/// before the failure, so they are only retried if `retry_non_idempotent` is set,
+/// and requests of a sequence are never retried. Streaming sessions are not retried.
+///
+/// When a call was retried, the error returned is `TritonError::Retried` wrapping
+/// the error of the last attempt.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: f64,
+    retryable_codes: Vec<Code>,
+    retry_non_idempotent: bool
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            multiplier: 2.0,
+            jitter: 0.2,
+            retryable_codes: vec![Code::Unavailable],
+            retry_non_idempotent: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Three attempts, backoff from 100 ms to 5 s with a 20% jitter, retrying `UNAVAILABLE`.
+    pub fn new() -> Self {
+        RetryPolicy::default()
+    }
+
+    /// Total number of attempts, including the first one.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Upper bound of the backoff before jitter, at most one hour.
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff.min(BACKOFF_LIMIT);
+        self
+    }
+
+    /// Factor applied to the backoff after each attempt.
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// Fraction of the backoff randomly added or removed, between 0 and 1 (NaN disables it).
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = if jitter.is_nan() { 0.0 } else { jitter.clamp(0.0, 1.0) };
+        self
+    }
+
+    /// gRPC status codes which are retried.
+    pub fn retryable_codes(mut self, codes: &[Code]) -> Self {
+        self.retryable_codes = codes.to_vec();
+        self
+    }
+
+    /// Also retry calls which may have side effects on the server: inference, model
+    /// loading and shared memory registration.
+    pub fn retry_non_idempotent(mut self, enabled: bool) -> Self {
+        self.retry_non_idempotent = enabled;
+        self
+    }
+
+    pub fn get_max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub(crate) fn allows(&self, class: RetryClass) -> bool {
+        match class {
+            RetryClass::Idempotent => true,
+            RetryClass::NonIdempotent => self.retry_non_idempotent,
+            RetryClass::Never => false,
+        }
+    }
+
+    pub fn is_retryable(&self, err: &TritonError) -> bool {
+        match err {
+            TritonError::Transport(_) => true,
+            TritonError::Status(status) => self.retryable_codes.contains(&status.code()),
+            _ => false,
+        }
+    }
+
+    /// Delay before the `retry`-th retry (starting at 1), jitter included.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.backoff_with(retry, random_unit())
+    }
+
+    // `unit` in [0, 1) picks the jitter, from -jitter to +jitter
+    fn backoff_with(&self, retry: u32, unit: f64) -> Duration {
+        let exponent = retry.saturating_sub(1).min(64) as i32;
+        let backoff = (self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent)).min(self.max_backoff.as_secs_f64());
+
+        Duration::try_from_secs_f64(backoff * (1.0 + self.jitter * (2.0 * unit - 1.0))).unwrap_or(self.max_backoff)
+    }
+}
+
+/// Whether a call can be sent again after a failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RetryClass {
+    Idempotent,
+    /// Only retried if the policy allows it.
+    NonIdempotent,
+    Never,
+}
+
+/// Retry class of a unary RPC of the inference protocol, by gRPC method name.
+///
+/// Only the RPCs listed as idempotent are always retried, other methods (e.g. inference,
+/// model loading or shared memory registration) may have been executed by a failed attempt.
+pub(crate) fn retry_class(method: &str) -> RetryClass {
+    match method {
+        "ServerLive" | "ServerReady" | "ModelReady" | "ServerMetadata" | "ModelMetadata" | "ModelConfig" | "ModelStatistics"
+        | "RepositoryIndex" | "RepositoryModelUnload" | "TraceSetting" | "LogSettings" | "SystemSharedMemoryStatus"
+        | "SystemSharedMemoryUnregister" | "CudaSharedMemoryStatus" | "CudaSharedMemoryUnregister" => RetryClass::Idempotent,
+        // Requests are consumed by the stream
+        "ModelStreamInfer" => RetryClass::Never,
+        _ => RetryClass::NonIdempotent,
+    }
+}
+
+// Uniform value in [0, 1), every RandomState is seeded differently
+fn random_unit() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(duration: Duration) -> f64 {
+        duration.as_secs_f64() * 1000.0
+    }
+
+    fn assert_close(actual: Duration, expected_millis: f64) {
+        assert!((millis(actual) - expected_millis).abs() < 1e-6, "{:?} != {} ms", actual, expected_millis);
+    }
+
+    #[test]
+    fn exponential_growth_and_cap() {
+        let policy = RetryPolicy::new().jitter(0.0).initial_backoff(Duration::from_millis(100)).max_backoff(Duration::from_secs(1));
+
+        let schedule: Vec<f64> = (1..=6).map(|retry| millis(policy.backoff(retry))).collect();
+        for (actual, expected) in schedule.iter().zip([100.0, 200.0, 400.0, 800.0, 1000.0, 1000.0]) {
+            assert!((actual - expected).abs() < 1e-6, "{:?}", schedule);
+        }
+
+        // Very large retry counts saturate at the cap
+        assert_close(policy.backoff(u32::MAX), 1000.0);
+    }
+
+    #[test]
+    fn multiplier() {
+        let policy = RetryPolicy::new().jitter(0.0).multiplier(3.0).initial_backoff(Duration::from_millis(10));
+        assert_close(policy.backoff(3), 90.0);
+
+        // Multipliers below 1 would shrink the backoff
+        let policy = RetryPolicy::new().jitter(0.0).multiplier(0.5);
+        assert_close(policy.backoff(4), 100.0);
+    }
+
+    #[test]
+    fn jitter_bounds() {
+        let policy = RetryPolicy::new().jitter(0.2).initial_backoff(Duration::from_millis(100));
+
+        assert_close(policy.backoff_with(1, 0.0), 80.0);
+        assert_close(policy.backoff_with(1, 0.5), 100.0);
+        assert!(millis(policy.backoff_with(1, 0.999_999)) < 120.0);
+        assert_close(policy.backoff_with(2, 0.0), 160.0);
+
+        for _ in 0..1000 {
+            let backoff = millis(policy.backoff(1));
+            assert!((80.0..120.0).contains(&backoff), "{}", backoff);
+        }
+
+        // Jitter is clamped to [0, 1]
+        assert_close(RetryPolicy::new().jitter(5.0).backoff_with(1, 0.0), 0.0);
+        assert_close(RetryPolicy::new().jitter(f64::NAN).backoff_with(1, 0.0), 100.0);
+    }
+
+    #[test]
+    fn extreme_settings() {
+        let policy = RetryPolicy::new().jitter(1.0).max_backoff(Duration::MAX).initial_backoff(Duration::MAX);
+        assert_eq!(policy.backoff_with(1, 0.5), BACKOFF_LIMIT);
+        assert_eq!(policy.backoff_with(u32::MAX, 0.999_999).as_secs(), 2 * BACKOFF_LIMIT.as_secs() - 1);
+
+        let policy = RetryPolicy::new().jitter(0.0).multiplier(f64::INFINITY);
+        assert_close(policy.backoff(1), 100.0);
+        assert_close(policy.backoff(2), 5000.0);
+
+        // inf * 0 is NaN, which is not a representable delay
+        let policy = RetryPolicy::new().jitter(0.0).multiplier(f64::INFINITY).initial_backoff(Duration::ZERO);
+        assert_eq!(policy.backoff(2), Duration::from_secs(5));
+        assert_eq!(RetryPolicy::new().multiplier(f64::NAN).jitter(0.0).backoff(3), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn attempts() {
+        assert_eq!(RetryPolicy::new().get_max_attempts(), 3);
+        assert_eq!(RetryPolicy::new().max_attempts(0).get_max_attempts(), 1);
+    }
+
+    #[test]
+    fn retryable_errors() {
+        let policy = RetryPolicy::new();
+        assert!(policy.is_retryable(&tonic::Status::unavailable("restarting").into()));
+        assert!(!policy.is_retryable(&tonic::Status::invalid_argument("bad input").into()));
+        assert!(!policy.is_retryable(&TritonError::Shape("bad shape".to_string())));
+
+        let policy = policy.retryable_codes(&[Code::ResourceExhausted]);
+        assert!(policy.is_retryable(&tonic::Status::resource_exhausted("queue full").into()));
+        assert!(!policy.is_retryable(&tonic::Status::unavailable("restarting").into()));
+    }
+
+    #[test]
+    fn rpc_classes() {
+        let idempotent = [
+            "ServerLive", "ServerReady", "ModelReady", "ServerMetadata", "ModelMetadata", "ModelConfig", "ModelStatistics",
+            "RepositoryIndex", "RepositoryModelUnload", "TraceSetting", "LogSettings", "SystemSharedMemoryStatus",
+            "SystemSharedMemoryUnregister", "CudaSharedMemoryStatus", "CudaSharedMemoryUnregister",
+        ];
+        for method in idempotent {
+            assert_eq!(retry_class(method), RetryClass::Idempotent, "{}", method);
+        }
+
+        for method in ["ModelInfer", "RepositoryModelLoad", "SystemSharedMemoryRegister", "CudaSharedMemoryRegister"] {
+            assert_eq!(retry_class(method), RetryClass::NonIdempotent, "{}", method);
+        }
+
+        assert_eq!(retry_class("ModelStreamInfer"), RetryClass::Never);
+
+        // Unknown or misspelled methods are not assumed to be free of side effects
+        for method in ["serverLive", "ModelInferAsync", ""] {
+            assert_eq!(retry_class(method), RetryClass::NonIdempotent, "{}", method);
+        }
+    }
+
+    #[test]
+    fn allowed_classes() {
+        let policy = RetryPolicy::new();
+        assert!(policy.allows(RetryClass::Idempotent));
+        assert!(!policy.allows(RetryClass::NonIdempotent));
+        assert!(!policy.allows(RetryClass::Never));
+
+        let policy = policy.retry_non_idempotent(true);
+        assert!(policy.allows(RetryClass::NonIdempotent));
+        assert!(!policy.allows(RetryClass::Never));
+    }
+}
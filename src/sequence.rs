@@ -0,0 +1,209 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+use std::collections::HashMap;
+
+use crate::error::TritonError;
+use crate::inference::{InferParameter, ModelInferRequest, infer_parameter};
+
+/// Correlation ID of a sequence, either numeric or string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SequenceId {
+    Numeric(u64),
+    Text(String),
+}
+
+impl From<u64> for SequenceId {
+    fn from(id: u64) -> Self {
+        SequenceId::Numeric(id)
+    }
+}
+
+impl From<&str> for SequenceId {
+    fn from(id: &str) -> Self {
+        SequenceId::Text(id.to_string())
+    }
+}
+
+impl From<String> for SequenceId {
+    fn from(id: String) -> Self {
+        SequenceId::Text(id)
+    }
+}
+
+/// Handle on a sequence of requests sent to a model using the sequence batcher.
+///
+/// Each tagged request carries the `sequence_id` parameter, the first one is marked
+/// with `sequence_start` and the one flagged as last with `sequence_end`.
+#[derive(Debug, Clone)]
+pub struct Sequence {
+    id: SequenceId,
+    started: bool,
+    ended: bool
+}
+
+impl Sequence {
+    pub fn new<I: Into<SequenceId>>(id: I) -> Self {
+        Sequence {
+            id: id.into(),
+            started: false,
+            ended: false
+        }
+    }
+
+    pub fn id(&self) -> &SequenceId {
+        &self.id
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    pub fn is_ended(&self) -> bool {
+        self.ended
+    }
+
+    /// Returns the sequence parameters of the next request and advances the sequence.
+    pub fn next_parameters(&mut self, last: bool) -> Result<HashMap<String, InferParameter>, TritonError> {
+        if self.ended {
+            return Err(TritonError::InvalidArgument(format!("sequence {:?} is already ended", self.id)));
+        }
+
+        let id_param = match &self.id {
+            SequenceId::Numeric(id) => {
+                let id = i64::try_from(*id).map_err(|_| TritonError::InvalidArgument(format!("sequence id {} does not fit in an int64 parameter", id)))?;
+                infer_parameter::ParameterChoice::Int64Param(id)
+            }
+            SequenceId::Text(id) => infer_parameter::ParameterChoice::StringParam(id.clone()),
+        };
+
+        let params = HashMap::from([
+            ("sequence_id".to_string(), InferParameter { parameter_choice: Some(id_param) }),
+            ("sequence_start".to_string(), InferParameter { parameter_choice: Some(infer_parameter::ParameterChoice::BoolParam(!self.started)) }),
+            ("sequence_end".to_string(), InferParameter { parameter_choice: Some(infer_parameter::ParameterChoice::BoolParam(last)) })
+        ]);
+
+        self.started = true;
+        self.ended = last;
+
+        Ok(params)
+    }
+
+    /// Adds the sequence parameters to a request and advances the sequence.
+    pub fn tag(&mut self, request: &mut ModelInferRequest, last: bool) -> Result<(), TritonError> {
+        let params = self.next_parameters(last)?;
+        request.parameters.extend(params);
+
+        Ok(())
+    }
+
+    // Used to roll back the sequence state when a tagged request could not be sent
+    pub(crate) fn restore(&mut self, started: bool, ended: bool) {
+        self.started = started;
+        self.ended = ended;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flag(params: &HashMap<String, InferParameter>, name: &str) -> bool {
+        match &params[name].parameter_choice {
+            Some(infer_parameter::ParameterChoice::BoolParam(value)) => *value,
+            other => panic!("{} is not a bool parameter: {:?}", name, other),
+        }
+    }
+
+    #[test]
+    fn start_and_end_flags() {
+        let mut sequence = Sequence::new(7);
+        assert!(!sequence.is_started());
+
+        let flags: Vec<(bool, bool)> = [false, false, true].iter()
+            .map(|last| sequence.next_parameters(*last).unwrap())
+            .map(|params| (flag(&params, "sequence_start"), flag(&params, "sequence_end")))
+            .collect();
+        assert_eq!(flags, [(true, false), (false, false), (false, true)]);
+
+        assert!(sequence.is_started() && sequence.is_ended());
+        assert!(matches!(sequence.next_parameters(false), Err(TritonError::InvalidArgument(_))));
+
+        // A single request both starts and ends the sequence
+        let params = Sequence::new(8).next_parameters(true).unwrap();
+        assert!(flag(&params, "sequence_start") && flag(&params, "sequence_end"));
+    }
+
+    #[test]
+    fn id_encoding() {
+        let params = Sequence::new(42).next_parameters(false).unwrap();
+        assert_eq!(params["sequence_id"].parameter_choice, Some(infer_parameter::ParameterChoice::Int64Param(42)));
+
+        let params = Sequence::new("session-1").next_parameters(false).unwrap();
+        assert_eq!(params["sequence_id"].parameter_choice, Some(infer_parameter::ParameterChoice::StringParam("session-1".to_string())));
+
+        // The id is checked before the sequence advances
+        let mut sequence = Sequence::new(u64::MAX);
+        assert!(matches!(sequence.next_parameters(false), Err(TritonError::InvalidArgument(_))));
+        assert!(!sequence.is_started());
+    }
+
+    #[test]
+    fn tag_request() {
+        let mut request = ModelInferRequest::default();
+        request.parameters.insert("priority".to_string(), InferParameter::default());
+
+        Sequence::new("s").tag(&mut request, false).unwrap();
+        let mut names: Vec<&str> = request.parameters.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        assert_eq!(names, ["priority", "sequence_end", "sequence_id", "sequence_start"]);
+    }
+
+    #[test]
+    fn restore_after_failure() {
+        let mut sequence = Sequence::new(1);
+
+        // The first request could not be sent, it is sent again as the start
+        let (started, ended) = (sequence.is_started(), sequence.is_ended());
+        sequence.next_parameters(true).unwrap();
+        sequence.restore(started, ended);
+        assert!(!sequence.is_started() && !sequence.is_ended());
+
+        let params = sequence.next_parameters(false).unwrap();
+        assert!(flag(&params, "sequence_start"));
+
+        // A failed last request leaves the sequence open
+        sequence.next_parameters(true).unwrap();
+        sequence.restore(true, false);
+        let params = sequence.next_parameters(true).unwrap();
+        assert!(!flag(&params, "sequence_start") && flag(&params, "sequence_end"));
+    }
+}
@@ -1,2 +1 @@
 #include "cuda_shared_memory/cuda_shared_memory.h"
-#include "system_shared_memory/shared_memory.h"
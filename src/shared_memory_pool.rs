@@ -0,0 +1,379 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+
+use std::collections::HashMap;
+use std::mem;
+use std::ptr;
+use std::sync::{Arc, Mutex};
+
+use ndarray::{ArrayBase, ArrayView, ArrayViewMut, Data, Dimension, IxDyn};
+
+use crate::error::TritonError;
+use crate::inference::InferParameter;
+use crate::request;
+use crate::system_shared_memory::{SystemSharedMemoryRegionHandle, element_pointer};
+use crate::tensor::FixedSizeElement;
+
+/// Alignment of the slices, enough for any tensor element type and for SIMD loads.
+pub const SLICE_ALIGNMENT: usize = 64;
+
+struct PoolRegion {
+    name: String,
+    addr: *mut u8,
+    byte_size: usize,
+    // Free (offset, size) ranges, sorted by offset and never adjacent
+    free: Mutex<Vec<(usize, usize)>>,
+    // Kept for its registration and mapping, released with the pool
+    _handle: SystemSharedMemoryRegionHandle
+}
+
+struct PoolInner {
+    regions: Vec<PoolRegion>
+}
+
+// Regions are written only through slices, which cover disjoint ranges, and the
+// free lists are behind mutexes.
+unsafe impl Send for PoolInner {}
+unsafe impl Sync for PoolInner {}
+
+/// Pool of registered system shared memory regions, sub-allocated into slices for the
+/// inputs and outputs of requests.
+///
+/// Registering a region is expensive, so the regions are created once and every
+/// request takes slices from them. A slice returns to the pool when it is dropped,
+/// and the regions are unregistered when the pool and all its slices are dropped.
+/// The pool is cheap to clone and can be shared by concurrent requests.
+#[derive(Clone)]
+pub struct SystemSharedMemoryPool {
+    inner: Arc<PoolInner>
+}
+
+impl SystemSharedMemoryPool {
+    /// Builds a pool from regions, usually created with `create_system_shared_memory`.
+    pub fn from_regions(regions: Vec<SystemSharedMemoryRegionHandle>) -> Self {
+        let regions = regions.into_iter().map(|mut handle| PoolRegion {
+            name: handle.get_name(),
+            addr: handle.as_mut_ptr(),
+            byte_size: handle.get_byte_size(),
+            free: Mutex::new(vec![(0, handle.get_byte_size())]),
+            _handle: handle,
+        }).collect();
+
+        SystemSharedMemoryPool { inner: Arc::new(PoolInner { regions }) }
+    }
+
+    /// Takes a slice of `byte_size` bytes from the first region with enough contiguous
+    /// free space. Fails with `TritonError::PoolExhausted` if there is none, allocation
+    /// does not wait for slices to be released.
+    pub fn allocate(&self, byte_size: usize) -> Result<SystemSharedMemorySlice, TritonError> {
+        let capacity = byte_size.max(1).checked_add(SLICE_ALIGNMENT - 1).map(|size| size / SLICE_ALIGNMENT * SLICE_ALIGNMENT)
+            .ok_or_else(|| TritonError::InvalidArgument(format!("slice of {} bytes is too large", byte_size)))?;
+
+        for (index, region) in self.inner.regions.iter().enumerate() {
+            let mut free = region.free.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+            if let Some(position) = free.iter().position(|(_, size)| *size >= capacity) {
+                let (offset, size) = free[position];
+                if size == capacity {
+                    free.remove(position);
+                } else {
+                    free[position] = (offset + capacity, size - capacity);
+                }
+
+                return Ok(SystemSharedMemorySlice { pool: self.inner.clone(), region: index, offset, byte_size, capacity });
+            }
+        }
+
+        Err(TritonError::PoolExhausted { requested: byte_size, available: self.available() })
+    }
+
+    /// Total size of the regions.
+    pub fn capacity(&self) -> usize {
+        self.inner.regions.iter().map(|region| region.byte_size).sum()
+    }
+
+    /// Total number of free bytes, possibly fragmented.
+    pub fn available(&self) -> usize {
+        self.inner.regions.iter()
+            .map(|region| region.free.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).iter().map(|(_, size)| size).sum::<usize>())
+            .sum()
+    }
+
+    pub fn region_names(&self) -> impl Iterator<Item = &str> {
+        self.inner.regions.iter().map(|region| region.name.as_str())
+    }
+}
+
+/// Range of a region of a `SystemSharedMemoryPool`, returned to the pool on drop.
+///
+/// The slice has to be kept alive until the response of the requests using it is read.
+pub struct SystemSharedMemorySlice {
+    pool: Arc<PoolInner>,
+    region: usize,
+    offset: usize,
+    byte_size: usize,
+    capacity: usize
+}
+
+impl SystemSharedMemorySlice {
+    fn pool_region(&self) -> &PoolRegion {
+        &self.pool.regions[self.region]
+    }
+
+    /// Name of the region in Triton.
+    pub fn region_name(&self) -> &str {
+        &self.pool_region().name
+    }
+
+    /// Offset of the slice in its region.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn byte_size(&self) -> usize {
+        self.byte_size
+    }
+
+    /// `shared_memory_region`, `shared_memory_offset` and `shared_memory_byte_size`
+    /// parameters of an input or output placed in this slice.
+    pub fn parameters(&self) -> Result<HashMap<String, InferParameter>, TritonError> {
+        request::shared_memory_parameters(self.region_name(), self.offset as u64, self.byte_size as u64)
+    }
+
+    pub(crate) fn as_mut_ptr(&self) -> *mut u8 {
+        unsafe { self.pool_region().addr.add(self.offset) }
+    }
+
+    fn check_bounds(&self, byte_size: usize, offset: usize) -> Result<(), TritonError> {
+        match offset.checked_add(byte_size) {
+            Some(end) if end <= self.byte_size => Ok(()),
+            _ => Err(TritonError::Shape(format!("{} bytes at offset {} do not fit in a shared memory slice of {} bytes", byte_size, offset, self.byte_size)))
+        }
+    }
+
+    /// Copies an array in logical order at `offset` in the slice.
+    pub fn copy_array<T: Copy, S: Data<Elem = T>, D: Dimension>(&mut self, array: &ArrayBase<S, D>, offset: usize) -> Result<(), TritonError> {
+        let byte_size = array.len() * mem::size_of::<T>();
+        self.check_bounds(byte_size, offset)?;
+
+        let destination = unsafe { self.as_mut_ptr().add(offset) };
+
+        if let Some(values) = array.as_slice() {
+            unsafe { ptr::copy_nonoverlapping(values.as_ptr() as *const u8, destination, byte_size) };
+            return Ok(());
+        }
+
+        let destination = destination as *mut T;
+        for (index, value) in array.iter().enumerate() {
+            unsafe { destination.add(index).write_unaligned(*value) };
+        }
+
+        Ok(())
+    }
+
+    /// Copies `count` elements at `offset` in the slice.
+    pub fn get_data<T: Copy>(&self, count: usize, offset: usize) -> Result<Vec<T>, TritonError> {
+        let byte_size = count.checked_mul(mem::size_of::<T>())
+            .ok_or_else(|| TritonError::InvalidArgument(format!("{} elements are too many", count)))?;
+        self.check_bounds(byte_size, offset)?;
+
+        let source = unsafe { self.as_mut_ptr().add(offset) } as *const T;
+
+        Ok((0..count).map(|index| unsafe { source.add(index).read_unaligned() }).collect())
+    }
+
+    /// Views `shape` elements at `offset` in place, see `SystemSharedMemoryRegionHandle::view`.
+    pub fn view<T: FixedSizeElement>(&self, offset: usize, shape: &[usize]) -> Result<ArrayView<'_, T, IxDyn>, TritonError> {
+        let data = element_pointer::<T>(self.as_mut_ptr(), self.byte_size, offset, shape)?;
+
+        Ok(unsafe { ArrayView::from_shape_ptr(IxDyn(shape), data) })
+    }
+
+    pub fn view_mut<T: FixedSizeElement>(&mut self, offset: usize, shape: &[usize]) -> Result<ArrayViewMut<'_, T, IxDyn>, TritonError> {
+        let data = element_pointer::<T>(self.as_mut_ptr(), self.byte_size, offset, shape)?;
+
+        Ok(unsafe { ArrayViewMut::from_shape_ptr(IxDyn(shape), data) })
+    }
+}
+
+impl Drop for SystemSharedMemorySlice {
+    fn drop(&mut self) {
+        let mut free = self.pool_region().free.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let position = free.partition_point(|(offset, _)| *offset < self.offset);
+        free.insert(position, (self.offset, self.capacity));
+
+        // Merges with the following then the preceding free range
+        if position + 1 < free.len() && free[position].0 + free[position].1 == free[position + 1].0 {
+            free[position].1 += free[position + 1].1;
+            free.remove(position + 1);
+        }
+        if position > 0 && free[position - 1].0 + free[position - 1].1 == free[position].0 {
+            free[position - 1].1 += free[position].1;
+            free.remove(position);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::process;
+
+    // Regions are not registered, no server is needed. Keys are unique to the process so
+    // that concurrent test runs do not collide.
+    fn pool(name: &str, sizes: &[usize]) -> SystemSharedMemoryPool {
+        let regions = sizes.iter().enumerate()
+            .map(|(index, size)| SystemSharedMemoryRegionHandle::create(&format!("{}_{}", name, index), &format!("/triton_rust_test_{}_{}_{}", name, process::id(), index), *size).unwrap())
+            .collect();
+
+        SystemSharedMemoryPool::from_regions(regions)
+    }
+
+    fn free(pool: &SystemSharedMemoryPool, region: usize) -> Vec<(usize, usize)> {
+        pool.inner.regions[region].free.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn alignment() {
+        let pool = pool("pool_alignment", &[4096]);
+
+        let slices: Vec<_> = [1, 63, 65, 100, 0].iter().map(|size| pool.allocate(*size).unwrap()).collect();
+        let offsets: Vec<_> = slices.iter().map(|slice| slice.offset()).collect();
+        assert_eq!(offsets, [0, 64, 128, 256, 384]);
+
+        for slice in &slices {
+            assert_eq!(slice.as_mut_ptr() as usize % SLICE_ALIGNMENT, 0);
+        }
+        assert_eq!(slices[2].byte_size(), 65);
+        assert_eq!(pool.available(), 4096 - 448);
+    }
+
+    #[test]
+    fn splitting() {
+        let pool = pool("pool_splitting", &[1024]);
+
+        let first = pool.allocate(100).unwrap();
+        assert_eq!(free(&pool, 0), [(128, 896)]);
+
+        // An exact fit removes the free range
+        let second = pool.allocate(896).unwrap();
+        assert_eq!(second.offset(), 128);
+        assert!(free(&pool, 0).is_empty());
+
+        drop((first, second));
+        assert_eq!(free(&pool, 0), [(0, 1024)]);
+    }
+
+    #[test]
+    fn merging() {
+        let pool = pool("pool_merging", &[256]);
+        let first = pool.allocate(64).unwrap();
+        let second = pool.allocate(64).unwrap();
+        let third = pool.allocate(64).unwrap();
+        assert_eq!(free(&pool, 0), [(192, 64)]);
+
+        drop(second);
+        assert_eq!(free(&pool, 0), [(64, 64), (192, 64)]);
+
+        // Merged with the following free range
+        drop(first);
+        assert_eq!(free(&pool, 0), [(0, 128), (192, 64)]);
+
+        // Merged with both neighbours
+        drop(third);
+        assert_eq!(free(&pool, 0), [(0, 256)]);
+    }
+
+    #[test]
+    fn exhaustion() {
+        let pool = pool("pool_exhaustion", &[128, 128]);
+        assert_eq!(pool.capacity(), 256);
+
+        let first = pool.allocate(128).unwrap();
+        let second = pool.allocate(100).unwrap();
+        assert_eq!((first.region_name(), second.region_name()), ("pool_exhaustion_0", "pool_exhaustion_1"));
+
+        assert!(matches!(pool.allocate(1), Err(TritonError::PoolExhausted { requested: 1, available: 0 })));
+        assert_eq!(pool.available(), 0);
+        drop(second);
+
+        // Free space is not contiguous across regions
+        assert!(matches!(pool.allocate(129), Err(TritonError::PoolExhausted { requested: 129, available: 128 })));
+        assert!(matches!(pool.allocate(usize::MAX), Err(TritonError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn reuse_after_free() {
+        let pool = pool("pool_reuse", &[128]);
+
+        let mut slice = pool.allocate(128).unwrap();
+        slice.copy_array(&ndarray::array![1u8, 2, 3], 0).unwrap();
+        assert!(pool.allocate(64).is_err());
+        drop(slice);
+
+        let slice = pool.allocate(64).unwrap();
+        assert_eq!((slice.region_name(), slice.offset()), ("pool_reuse_0", 0));
+        assert_eq!(slice.get_data::<u8>(3, 0).unwrap(), [1, 2, 3]);
+
+        // A slice keeps the regions alive after the pool is dropped
+        drop(pool);
+        assert_eq!(slice.get_data::<u8>(3, 0).unwrap(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn concurrent_allocations() {
+        // Each thread holds at most one slice of 256 bytes, the largest free range always fits one
+        let pool = pool("pool_concurrent", &[4096]);
+
+        let threads: Vec<_> = (0..8u8).map(|thread| {
+            let pool = pool.clone();
+            std::thread::spawn(move || {
+                for iteration in 0..200 {
+                    let size = 1 + (iteration * 37 + thread as usize * 11) % 256;
+                    let mut slice = pool.allocate(size).unwrap();
+
+                    let pattern = ndarray::Array1::from_elem(size, thread);
+                    slice.copy_array(&pattern, 0).unwrap();
+                    std::thread::yield_now();
+                    assert!(slice.get_data::<u8>(size, 0).unwrap().iter().all(|value| *value == thread), "slices overlap");
+                }
+            })
+        }).collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert_eq!(free(&pool, 0), [(0, 4096)]);
+    }
+}
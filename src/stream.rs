@@ -0,0 +1,241 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+use std::future::Future;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+use tokio_stream::{Stream, StreamExt};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+use crate::error::TritonError;
+use crate::request::InferRequest;
+use crate::response::InferResponse;
+use crate::sequence::Sequence;
+use crate::inference::grpc_inference_service_client::GrpcInferenceServiceClient;
+use crate::inference::{ModelInferRequest, ModelInferResponse, ModelStreamInferResponse, infer_parameter};
+
+type StreamingCall = Pin<Box<dyn Future<Output = Result<tonic::Response<tonic::Streaming<ModelStreamInferResponse>>, tonic::Status>> + Send>>;
+
+/// Sending half of a streaming inference session.
+///
+/// Requests are queued until the session is closed, which happens when every
+/// sender has been dropped.
+#[derive(Clone)]
+pub struct InferStreamSender {
+    tx: mpsc::UnboundedSender<ModelInferRequest>
+}
+
+impl InferStreamSender {
+    pub(crate) fn new(tx: mpsc::UnboundedSender<ModelInferRequest>) -> Self {
+        InferStreamSender { tx }
+    }
+
+    pub fn send(&self, request: InferRequest) -> Result<(), TritonError> {
+        self.send_raw(request.build()?)
+    }
+
+    pub fn send_raw(&self, request: ModelInferRequest) -> Result<(), TritonError> {
+        self.tx.send(request).map_err(|_| TritonError::InvalidArgument("the inference stream is closed".to_string()))
+    }
+
+    /// Sends a request as part of a sequence, `last` marks the end of the sequence.
+    pub fn send_sequence(&self, sequence: &mut Sequence, request: InferRequest, last: bool) -> Result<(), TritonError> {
+        let mut request = request.build()?;
+        let (started, ended) = (sequence.is_started(), sequence.is_ended());
+        sequence.tag(&mut request, last)?;
+
+        let result = self.send_raw(request);
+        if result.is_err() {
+            sequence.restore(started, ended);
+        }
+
+        result
+    }
+}
+
+/// One response of a streaming inference session.
+#[derive(Debug, Clone)]
+pub struct StreamInferResponse {
+    response: InferResponse
+}
+
+impl StreamInferResponse {
+    /// Returns true if this is the last response for its request (`triton_final_response`).
+    pub fn is_final(&self) -> bool {
+        match self.response.parameters.get("triton_final_response").and_then(|param| param.parameter_choice.as_ref()) {
+            Some(infer_parameter::ParameterChoice::BoolParam(value)) => *value,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the response carries no output, as for the empty final
+    /// responses sent by decoupled models.
+    pub fn is_empty(&self) -> bool {
+        self.response.outputs.is_empty()
+    }
+
+    pub fn into_inner(self) -> ModelInferResponse {
+        self.response.into_inner()
+    }
+}
+
+impl Deref for StreamInferResponse {
+    type Target = InferResponse;
+
+    fn deref(&self) -> &InferResponse {
+        &self.response
+    }
+}
+
+enum StreamState {
+    Pending(StreamingCall),
+    Open(Box<tonic::Streaming<ModelStreamInferResponse>>),
+    Closed,
+}
+
+/// Receiving half of a streaming inference session.
+///
+/// The gRPC call is only started when the stream is first polled, so requests can
+/// be queued on the sender beforehand. Errors reported by the server for a single
+/// request are yielded as `TritonError::Inference` and do not end the stream.
+pub struct InferStream {
+    state: StreamState
+}
+
+impl InferStream {
+    pub(crate) fn new(call: StreamingCall) -> Self {
+        InferStream { state: StreamState::Pending(call) }
+    }
+}
+
+impl Stream for InferStream {
+    type Item = Result<StreamInferResponse, TritonError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match &mut self.state {
+                StreamState::Pending(call) => {
+                    match call.as_mut().poll(cx) {
+                        Poll::Pending => return Poll::Pending,
+                        Poll::Ready(Ok(response)) => self.state = StreamState::Open(Box::new(response.into_inner())),
+                        Poll::Ready(Err(status)) => {
+                            self.state = StreamState::Closed;
+                            return Poll::Ready(Some(Err(status.into())));
+                        }
+                    }
+                }
+                StreamState::Open(streaming) => {
+                    return match Pin::new(streaming.as_mut()).poll_next(cx) {
+                        Poll::Pending => Poll::Pending,
+                        Poll::Ready(None) => {
+                            self.state = StreamState::Closed;
+                            Poll::Ready(None)
+                        }
+                        Poll::Ready(Some(Err(status))) => Poll::Ready(Some(Err(status.into()))),
+                        Poll::Ready(Some(Ok(response))) => Poll::Ready(Some(from_stream_response(response))),
+                    };
+                }
+                StreamState::Closed => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+fn from_stream_response(response: ModelStreamInferResponse) -> Result<StreamInferResponse, TritonError> {
+    let infer_response = response.infer_response.unwrap_or_default();
+
+    if !response.error_message.is_empty() {
+        return Err(TritonError::Inference { request_id: infer_response.id, message: response.error_message });
+    }
+
+    Ok(StreamInferResponse { response: InferResponse::new(infer_response) })
+}
+
+// `header` carries the metadata of the call, an interceptor error is reported on the first poll
+pub(crate) fn open(mut client: GrpcInferenceServiceClient<tonic::transport::Channel>, header: Result<tonic::Request<()>, tonic::Status>) -> (InferStreamSender, InferStream) {
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    let call: StreamingCall = Box::pin(async move {
+        let (metadata, extensions, ()) = header?.into_parts();
+        client.model_stream_infer(tonic::Request::from_parts(metadata, extensions, UnboundedReceiverStream::new(rx))).await
+    });
+
+    (InferStreamSender::new(tx), InferStream::new(call))
+}
+
+/// Blocking version of a streaming inference session, driven by the runtime of `TritonInference`.
+pub struct BlockingInferStream<'a> {
+    rt: &'a Runtime,
+    sender: InferStreamSender,
+    stream: InferStream
+}
+
+impl<'a> BlockingInferStream<'a> {
+    pub(crate) fn new(rt: &'a Runtime, sender: InferStreamSender, stream: InferStream) -> Self {
+        BlockingInferStream { rt, sender, stream }
+    }
+
+    pub fn send(&self, request: InferRequest) -> Result<(), TritonError> {
+        self.sender.send(request)
+    }
+
+    pub fn send_raw(&self, request: ModelInferRequest) -> Result<(), TritonError> {
+        self.sender.send_raw(request)
+    }
+
+    pub fn send_sequence(&self, sequence: &mut Sequence, request: InferRequest, last: bool) -> Result<(), TritonError> {
+        self.sender.send_sequence(sequence, request, last)
+    }
+
+    pub fn sender(&self) -> InferStreamSender {
+        self.sender.clone()
+    }
+
+    /// Closes the sending half and returns the remaining responses.
+    pub fn into_responses(self) -> impl Iterator<Item = Result<StreamInferResponse, TritonError>> + 'a {
+        let BlockingInferStream { rt, sender, mut stream } = self;
+        drop(sender);
+
+        std::iter::from_fn(move || rt.block_on(stream.next()))
+    }
+}
+
+impl Iterator for BlockingInferStream<'_> {
+    type Item = Result<StreamInferResponse, TritonError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.rt.block_on(self.stream.next())
+    }
+}
@@ -28,100 +28,342 @@ same conditions as regards security.
 The fact that you are presently reading this means that you have had
 knowledge of the CeCILL-B license and that you accept its terms.*/
 
-#![allow(non_upper_case_globals)]
-#![allow(non_camel_case_types)]
-#![allow(non_snake_case)]
-#![allow(dead_code)]
 
-use std::ffi::{CString};
-use std::os::raw::{c_void, c_char, c_int};
+
+use std::ffi::CString;
+use std::io;
 use std::mem;
-use std::slice;
+use std::ptr;
 
-use ndarray::{ArrayBase, RawData, Dimension};
+use ndarray::{ArrayBase, ArrayView, ArrayViewMut, Data, Dimension, IxDyn};
 
-include!(concat!(env!("OUT_DIR"), "/shared_memory_binding.rs"));
+use crate::error::TritonError;
+use crate::registration::Registration;
+use crate::tensor::FixedSizeElement;
 
+/// POSIX shared memory region (`shm_open` + `mmap`) registered to Triton as system shared memory.
+///
+/// Dropping the handle unmaps the region, unlinks the shared memory objects it created and,
+/// for regions created by a client, unregisters it from the server (best-effort, errors are
+/// ignored). Inside an async context the unregistration is only started on drop, use
+/// `unregister` to wait for it. Use `leak` or `into_raw` to keep the region alive.
 pub struct SystemSharedMemoryRegionHandle {
     name: String,
     key: String,
-    handle: *mut c_void
+    addr: *mut u8,
+    byte_size: usize,
+    // The shared memory object was created by this handle and is unlinked with it
+    owned: bool,
+    registration: Option<Registration>
+}
+
+// The mapping is owned by the handle, it can be moved to another thread
+// (e.g. across an await point of the async client).
+unsafe impl Send for SystemSharedMemoryRegionHandle {}
+
+fn os_error(operation: &'static str) -> TritonError {
+    TritonError::SharedMemory { operation, code: io::Error::last_os_error().raw_os_error().unwrap_or(-1) }
+}
+
+// Address of `shape` elements of `T` at `offset` in a mapping of `byte_size` bytes, checking bounds and alignment
+pub(crate) fn element_pointer<T>(addr: *mut u8, byte_size: usize, offset: usize, shape: &[usize]) -> Result<*mut T, TritonError> {
+    let length = shape.iter().try_fold(mem::size_of::<T>(), |length, dim| length.checked_mul(*dim));
+
+    match length.and_then(|length| offset.checked_add(length)) {
+        Some(end) if end <= byte_size => (),
+        _ => return Err(TritonError::Shape(format!("{:?} elements of {} bytes at offset {} do not fit in {} bytes of shared memory", shape, mem::size_of::<T>(), offset, byte_size)))
+    }
+
+    let pointer = unsafe { addr.add(offset) };
+    if (pointer as usize) & (mem::align_of::<T>() - 1) != 0 {
+        return Err(TritonError::InvalidArgument(format!("offset {} is not aligned on {} bytes", offset, mem::align_of::<T>())));
+    }
+
+    Ok(pointer as *mut T)
 }
 
 impl SystemSharedMemoryRegionHandle {
-    pub fn create(triton_shm_name: &'static str, shm_key: &'static str, size: usize) -> Self {
-
-        let c_triton_shm_name = CString::new(triton_shm_name).unwrap();
-        let c_shm_key = CString::new(shm_key).unwrap();
-        let mut handle: *mut c_void = std::ptr::null_mut();
-
-        let _result = unsafe {
-            SharedMemoryRegionCreate(
-                c_triton_shm_name.as_ptr(),
-                c_shm_key.as_ptr(),
-                size,
-                &mut handle
-            )
+    /// Creates the shared memory object `shm_key` (e.g. `/input_data`) with `size` bytes
+    /// and maps it. `triton_shm_name` is the name of the region in Triton.
+    ///
+    /// Fails if the object already exists, use `open` to map an existing one.
+    pub fn create(triton_shm_name: &str, shm_key: &str, size: usize) -> Result<Self, TritonError> {
+        Self::map(triton_shm_name, shm_key, size, true)
+    }
+
+    /// Maps the first `size` bytes of the existing shared memory object `shm_key`. The object
+    /// is not unlinked when the handle is dropped or destroyed.
+    pub fn open(triton_shm_name: &str, shm_key: &str, size: usize) -> Result<Self, TritonError> {
+        Self::map(triton_shm_name, shm_key, size, false)
+    }
+
+    fn map(triton_shm_name: &str, shm_key: &str, size: usize, create: bool) -> Result<Self, TritonError> {
+
+        let c_shm_key = CString::new(shm_key)?;
+        let length = libc::off_t::try_from(size).map_err(|_| TritonError::InvalidArgument(format!("size {} is too large", size)))?;
+
+        let flags = if create { libc::O_RDWR | libc::O_CREAT | libc::O_EXCL } else { libc::O_RDWR };
+        let fd = unsafe { libc::shm_open(c_shm_key.as_ptr(), flags, (libc::S_IRUSR | libc::S_IWUSR) as libc::mode_t) };
+        if fd == -1 {
+            let err = io::Error::last_os_error();
+            return Err(match err.raw_os_error() {
+                Some(libc::EEXIST) => TritonError::InvalidArgument(format!("shared memory object {} already exists", shm_key)),
+                code => TritonError::SharedMemory { operation: "shm_open", code: code.unwrap_or(-1) },
+            });
+        }
+
+        let sized = if create {
+            // A new shared memory object is empty
+            if unsafe { libc::ftruncate(fd, length) } == -1 { Err(os_error("ftruncate")) } else { Ok(()) }
+        } else {
+            // Accessing the mapping past the end of the object raises SIGBUS
+            let mut stat: libc::stat = unsafe { mem::zeroed() };
+            if unsafe { libc::fstat(fd, &mut stat) } == -1 {
+                Err(os_error("fstat"))
+            } else if stat.st_size < length {
+                Err(TritonError::InvalidArgument(format!("shared memory object {} has {} bytes, {} requested", shm_key, stat.st_size, size)))
+            } else {
+                Ok(())
+            }
         };
 
-        SystemSharedMemoryRegionHandle {
+        if let Err(err) = sized {
+            unsafe { libc::close(fd) };
+            if create {
+                unsafe { libc::shm_unlink(c_shm_key.as_ptr()) };
+            }
+            return Err(err);
+        }
+
+        let addr = unsafe { libc::mmap(ptr::null_mut(), size, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED, fd, 0) };
+        let map_error = if addr == libc::MAP_FAILED { Some(os_error("mmap")) } else { None };
+
+        // The mapping stays valid once the descriptor is closed
+        unsafe { libc::close(fd) };
+        if let Some(err) = map_error {
+            if create {
+                unsafe { libc::shm_unlink(c_shm_key.as_ptr()) };
+            }
+            return Err(err);
+        }
+
+        Ok(SystemSharedMemoryRegionHandle {
             name: triton_shm_name.to_string(),
             key: shm_key.to_string(),
-            handle: handle,
+            addr: addr as *mut u8,
+            byte_size: size,
+            owned: create,
+            registration: None,
+        })
+    }
+
+    pub(crate) fn set_registration(&mut self, registration: Registration) {
+        self.registration = Some(registration);
+    }
+
+    pub(crate) fn registration_mut(&mut self) -> Option<&mut Registration> {
+        self.registration.as_mut()
+    }
+
+    /// Unregisters the region from the server, waiting for the answer, then releases it as
+    /// on drop. Does nothing for a region that is not registered by a client.
+    pub async fn unregister(mut self) -> Result<(), TritonError> {
+        let name = self.name.clone();
+        match self.registration.take() {
+            Some(registration) => registration.unregister(&name).await,
+            None => Ok(()),
         }
     }
 
+    /// Keeps the region mapped, its shared memory object and its registration on the
+    /// server for the lifetime of the process.
+    pub fn leak(mut self) {
+        self.leak_in_place();
+    }
+
+    /// Releases the ownership of the region without unmapping it, returning its address
+    /// and size. The caller becomes responsible for `munmap`, `shm_unlink` and for
+    /// unregistering the region from the server.
+    pub fn into_raw(mut self) -> (*mut u8, usize) {
+        let raw = (self.addr, self.byte_size);
+        self.leak_in_place();
+        raw
+    }
+
+    fn leak_in_place(&mut self) {
+        self.registration = None;
+        self.addr = ptr::null_mut();
+    }
+
     pub fn get_name(&mut self) -> String {
         self.name.clone()
     }
 
-    pub fn destroy(&mut self) {
-        let _result = unsafe {
-            SharedMemoryRegionDestroy(
-                self.handle
-            )
-        };
+    pub fn get_key(&self) -> &str {
+        &self.key
     }
 
-    pub fn copy_array<T: RawData, D: Dimension>(&mut self, array: &ArrayBase<T, D>, offset: usize) {
+    pub fn get_byte_size(&self) -> usize {
+        self.byte_size
+    }
 
-        let byte_size = array.shape().iter().product::<usize>() * mem::size_of::<T::Elem>();
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.addr
+    }
 
-        let _result = unsafe { SharedMemoryRegionSet(
-                self.handle,
-                offset,
-                byte_size,
-                array.as_ptr() as *const c_void
-            )
-        };
+    /// Unmaps the region and removes the shared memory object if it was created by `create`.
+    /// The region stays registered on the server until the handle is dropped.
+    pub fn destroy(&mut self) -> Result<(), TritonError> {
+        if self.addr.is_null() {
+            return Ok(());
+        }
+
+        let result = unsafe { libc::munmap(self.addr as *mut libc::c_void, self.byte_size) };
+        if result == -1 {
+            return Err(os_error("munmap"));
+        }
+        self.addr = ptr::null_mut();
+
+        if !self.owned {
+            return Ok(());
+        }
+
+        let c_shm_key = CString::new(self.key.as_str())?;
+        if unsafe { libc::shm_unlink(c_shm_key.as_ptr()) } == -1 {
+            return Err(os_error("shm_unlink"));
+        }
+
+        Ok(())
     }
 
-    pub fn get_data<T: Copy>(&mut self, size: u64, offset: u64) -> Vec<T> {
+    fn check_bounds(&self, byte_size: usize, offset: usize) -> Result<(), TritonError> {
+        if self.addr.is_null() {
+            return Err(TritonError::InvalidArgument(format!("shared memory region {} is destroyed", self.name)));
+        }
 
-        let mut shm_addr: *mut c_char = std::ptr::null_mut();
-        let mut shm_key = mem::MaybeUninit::<*const c_char>::uninit();
-        let mut fd: c_int = 0;
-        let mut size_val: usize = 0;
-        let mut offset_val: usize = 0;
+        match offset.checked_add(byte_size) {
+            Some(end) if end <= self.byte_size => Ok(()),
+            _ => Err(TritonError::Shape(format!("{} bytes at offset {} do not fit in shared memory region {} of {} bytes", byte_size, offset, self.name, self.byte_size)))
+        }
+    }
 
-        let _result = unsafe { GetSharedMemoryHandleInfo(
-                self.handle,
-                &mut shm_addr,
-                shm_key.as_mut_ptr(),
-                &mut fd,
-                &mut size_val,
-                &mut offset_val
-            )
-        };
+    /// Copies an array in logical order at `offset` in the region.
+    ///
+    /// Arrays in standard layout are copied at once, other layouts are written
+    /// element by element directly into the region.
+    pub fn copy_array<T: Copy, S: Data<Elem = T>, D: Dimension>(&mut self, array: &ArrayBase<S, D>, offset: usize) -> Result<(), TritonError> {
 
-        let result = unsafe {
-            slice::from_raw_parts(shm_addr.add(offset.try_into().unwrap()) as *mut u8, size.try_into().unwrap())
-        };
+        let byte_size = array.len() * mem::size_of::<T>();
+        self.check_bounds(byte_size, offset)?;
+
+        let destination = unsafe { self.addr.add(offset) };
+
+        if let Some(values) = array.as_slice() {
+            unsafe { ptr::copy_nonoverlapping(values.as_ptr() as *const u8, destination, byte_size) };
+            return Ok(());
+        }
+
+        let destination = destination as *mut T;
+        for (index, value) in array.iter().enumerate() {
+            unsafe { destination.add(index).write_unaligned(*value) };
+        }
+
+        Ok(())
+    }
+
+    /// Copies the elements held in `size` bytes at `offset`, which does not have to be aligned.
+    pub fn get_data<T: Copy>(&mut self, size: u64, offset: u64) -> Result<Vec<T>, TritonError> {
+
+        let size = usize::try_from(size).map_err(|_| TritonError::InvalidArgument(format!("size {} is too large", size)))?;
+        let offset = usize::try_from(offset).map_err(|_| TritonError::InvalidArgument(format!("offset {} is too large", offset)))?;
+        self.check_bounds(size, offset)?;
+
+        let source = unsafe { self.addr.add(offset) } as *const T;
+        let count = size / mem::size_of::<T>();
+
+        Ok((0..count).map(|index| unsafe { source.add(index).read_unaligned() }).collect())
+    }
+
+    /// Views `shape` elements at `offset` in place, in standard layout. `offset` must be
+    /// aligned for `T`.
+    ///
+    /// The server must not write to this part of the region while the view is alive,
+    /// i.e. outputs are read once their response is received.
+    pub fn view<T: FixedSizeElement>(&self, offset: usize, shape: &[usize]) -> Result<ArrayView<'_, T, IxDyn>, TritonError> {
+        self.check_bounds(0, offset)?;
+        let data = element_pointer::<T>(self.addr, self.byte_size, offset, shape)?;
+
+        Ok(unsafe { ArrayView::from_shape_ptr(IxDyn(shape), data) })
+    }
+
+    /// Mutable version of `view`, to write an input in place.
+    pub fn view_mut<T: FixedSizeElement>(&mut self, offset: usize, shape: &[usize]) -> Result<ArrayViewMut<'_, T, IxDyn>, TritonError> {
+        self.check_bounds(0, offset)?;
+        let data = element_pointer::<T>(self.addr, self.byte_size, offset, shape)?;
+
+        Ok(unsafe { ArrayViewMut::from_shape_ptr(IxDyn(shape), data) })
+    }
+}
+
+impl Drop for SystemSharedMemoryRegionHandle {
+    fn drop(&mut self) {
+        if let Some(registration) = self.registration.take() {
+            registration.unregister_best_effort(self.name.clone());
+        }
+
+        let _ = self.destroy();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::process;
+
+    // Unique to the process so that concurrent test runs do not collide
+    fn key(name: &str) -> String {
+        format!("/triton_rust_test_{}_{}", name, process::id())
+    }
+
+    fn exists(key: &str) -> bool {
+        std::path::Path::new("/dev/shm").join(key.trim_start_matches('/')).exists()
+    }
+
+    #[test]
+    fn create_is_exclusive() {
+        let key = key("excl");
+        let mut handle = SystemSharedMemoryRegionHandle::create("excl", &key, 64).unwrap();
+
+        let err = SystemSharedMemoryRegionHandle::create("excl", &key, 64).err().unwrap();
+        assert!(err.to_string().contains("already exists"), "{}", err);
+
+        // The failed attempt does not remove the object of the first handle
+        handle.copy_array(&ndarray::array![1.0f32, 2.0], 0).unwrap();
+        assert_eq!(handle.get_data::<f32>(8, 0).unwrap(), [1.0, 2.0]);
+
+        drop(handle);
+        assert!(!exists(&key));
+    }
+
+    #[test]
+    fn open_existing() {
+        let key = key("open");
+        let mut handle = SystemSharedMemoryRegionHandle::create("open", &key, 64).unwrap();
+        handle.copy_array(&ndarray::array![3i32, 4], 8).unwrap();
+
+        let mut opened = SystemSharedMemoryRegionHandle::open("open", &key, 16).unwrap();
+        assert_eq!(opened.get_data::<i32>(8, 8).unwrap(), [3, 4]);
+
+        // Dropping the opened handle keeps the object
+        opened.destroy().unwrap();
+        drop(opened);
+        assert!(exists(&key));
 
-        let result_vec_ref = result.to_vec();
-        let result_vec_ref_T = unsafe { result_vec_ref.align_to::<T>().1 };
-        let result_vec: Vec<T> = result_vec_ref_T.iter().map(|x: &T| *x).collect();
+        assert!(SystemSharedMemoryRegionHandle::open("open", &key, 128).is_err());
 
-        result_vec
+        drop(handle);
+        assert!(!exists(&key));
+        assert!(matches!(SystemSharedMemoryRegionHandle::open("open", &key, 16), Err(TritonError::SharedMemory { operation: "shm_open", .. })));
     }
 }
@@ -0,0 +1,583 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use ndarray::{ArrayBase, ArrayD, Data, Dimension, IxDyn};
+#[cfg(feature = "half")]
+use ndarray::Array;
+#[cfg(feature = "half")]
+use half::{f16, bf16};
+
+use crate::error::TritonError;
+use crate::inference::InferParameter;
+use crate::inference::model_infer_request::InferInputTensor;
+
+/// Tensor datatypes supported by Triton.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DataType {
+    Bool,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Fp16,
+    Bf16,
+    Fp32,
+    Fp64,
+    Bytes,
+}
+
+impl DataType {
+    /// Name of the datatype in the inference protocol.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataType::Bool => "BOOL",
+            DataType::UInt8 => "UINT8",
+            DataType::UInt16 => "UINT16",
+            DataType::UInt32 => "UINT32",
+            DataType::UInt64 => "UINT64",
+            DataType::Int8 => "INT8",
+            DataType::Int16 => "INT16",
+            DataType::Int32 => "INT32",
+            DataType::Int64 => "INT64",
+            DataType::Fp16 => "FP16",
+            DataType::Bf16 => "BF16",
+            DataType::Fp32 => "FP32",
+            DataType::Fp64 => "FP64",
+            DataType::Bytes => "BYTES",
+        }
+    }
+
+    /// Size in bytes of one element, `None` for variable-size BYTES elements.
+    pub fn size(&self) -> Option<usize> {
+        match self {
+            DataType::Bool | DataType::UInt8 | DataType::Int8 => Some(1),
+            DataType::UInt16 | DataType::Int16 | DataType::Fp16 | DataType::Bf16 => Some(2),
+            DataType::UInt32 | DataType::Int32 | DataType::Fp32 => Some(4),
+            DataType::UInt64 | DataType::Int64 | DataType::Fp64 => Some(8),
+            DataType::Bytes => None,
+        }
+    }
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DataType {
+    type Err = TritonError;
+
+    fn from_str(datatype: &str) -> Result<Self, TritonError> {
+        match datatype {
+            "BOOL" => Ok(DataType::Bool),
+            "UINT8" => Ok(DataType::UInt8),
+            "UINT16" => Ok(DataType::UInt16),
+            "UINT32" => Ok(DataType::UInt32),
+            "UINT64" => Ok(DataType::UInt64),
+            "INT8" => Ok(DataType::Int8),
+            "INT16" => Ok(DataType::Int16),
+            "INT32" => Ok(DataType::Int32),
+            "INT64" => Ok(DataType::Int64),
+            "FP16" => Ok(DataType::Fp16),
+            "BF16" => Ok(DataType::Bf16),
+            "FP32" => Ok(DataType::Fp32),
+            "FP64" => Ok(DataType::Fp64),
+            "BYTES" => Ok(DataType::Bytes),
+            _ => Err(TritonError::DataType(format!("unknown datatype '{}'", datatype))),
+        }
+    }
+}
+
+/// Rust element types which can be sent to and received from Triton.
+///
+/// Elements are serialized in little-endian order as expected by the server.
+pub trait TensorElement: Sized + Clone {
+    const DATATYPE: DataType;
+
+    fn encode<'a, I: Iterator<Item = &'a Self>>(values: I, output: &mut Vec<u8>) -> Result<(), TritonError> where Self: 'a;
+
+    /// Serializes contiguous elements, fixed-size types override it with a single copy.
+    fn encode_slice(values: &[Self], output: &mut Vec<u8>) -> Result<(), TritonError> {
+        Self::encode(values.iter(), output)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Vec<Self>, TritonError>;
+}
+
+/// Fixed-size elements for which every bit pattern is a valid value, so they can be
+/// viewed in place in shared memory (in native byte order).
+///
+/// # Safety
+///
+/// Implementors must be `Copy`, without padding and valid for any bit pattern.
+pub unsafe trait FixedSizeElement: TensorElement + Copy {}
+
+macro_rules! impl_tensor_element {
+    ($type:ty, $datatype:expr) => {
+        unsafe impl FixedSizeElement for $type {}
+
+        impl TensorElement for $type {
+            const DATATYPE: DataType = $datatype;
+
+            fn encode<'a, I: Iterator<Item = &'a Self>>(values: I, output: &mut Vec<u8>) -> Result<(), TritonError> {
+                for value in values {
+                    output.extend_from_slice(&value.to_le_bytes());
+                }
+                Ok(())
+            }
+
+            #[cfg(target_endian = "little")]
+            fn encode_slice(values: &[Self], output: &mut Vec<u8>) -> Result<(), TritonError> {
+                // In-memory representation is already the little-endian wire format
+                let bytes = unsafe { std::slice::from_raw_parts(values.as_ptr() as *const u8, std::mem::size_of_val(values)) };
+                output.extend_from_slice(bytes);
+                Ok(())
+            }
+
+            fn decode(bytes: &[u8]) -> Result<Vec<Self>, TritonError> {
+                let element_size = std::mem::size_of::<$type>();
+                check_byte_length(bytes.len(), element_size, $datatype)?;
+
+                Ok(bytes.chunks_exact(element_size).map(|chunk| <$type>::from_le_bytes(chunk.try_into().unwrap())).collect())
+            }
+        }
+    };
+}
+
+impl_tensor_element!(u8, DataType::UInt8);
+impl_tensor_element!(u16, DataType::UInt16);
+impl_tensor_element!(u32, DataType::UInt32);
+impl_tensor_element!(u64, DataType::UInt64);
+impl_tensor_element!(i8, DataType::Int8);
+impl_tensor_element!(i16, DataType::Int16);
+impl_tensor_element!(i32, DataType::Int32);
+impl_tensor_element!(i64, DataType::Int64);
+impl_tensor_element!(f32, DataType::Fp32);
+impl_tensor_element!(f64, DataType::Fp64);
+#[cfg(feature = "half")]
+impl_tensor_element!(f16, DataType::Fp16);
+#[cfg(feature = "half")]
+impl_tensor_element!(bf16, DataType::Bf16);
+
+/// Converts an f32 array to half precision (FP16).
+#[cfg(feature = "half")]
+pub fn f32_to_f16<S: Data<Elem = f32>, D: Dimension>(array: &ArrayBase<S, D>) -> Array<f16, D> {
+    array.mapv(f16::from_f32)
+}
+
+/// Converts an f32 array to brain floating point (BF16).
+#[cfg(feature = "half")]
+pub fn f32_to_bf16<S: Data<Elem = f32>, D: Dimension>(array: &ArrayBase<S, D>) -> Array<bf16, D> {
+    array.mapv(bf16::from_f32)
+}
+
+#[cfg(feature = "half")]
+pub fn f16_to_f32<S: Data<Elem = f16>, D: Dimension>(array: &ArrayBase<S, D>) -> Array<f32, D> {
+    array.mapv(f16::to_f32)
+}
+
+#[cfg(feature = "half")]
+pub fn bf16_to_f32<S: Data<Elem = bf16>, D: Dimension>(array: &ArrayBase<S, D>) -> Array<f32, D> {
+    array.mapv(bf16::to_f32)
+}
+
+impl TensorElement for bool {
+    const DATATYPE: DataType = DataType::Bool;
+
+    fn encode<'a, I: Iterator<Item = &'a Self>>(values: I, output: &mut Vec<u8>) -> Result<(), TritonError> {
+        output.extend(values.map(|value| *value as u8));
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Vec<Self>, TritonError> {
+        bytes.iter().map(|byte| match byte {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(TritonError::DataType(format!("invalid BOOL value {}", byte))),
+        }).collect()
+    }
+}
+
+fn bytes_element_length(length: usize) -> Result<u32, TritonError> {
+    u32::try_from(length).map_err(|_| TritonError::DataType(format!("BYTES element of {} bytes exceeds the 4 GiB limit", length)))
+}
+
+/// Serializes BYTES elements: each element is prefixed by its length as a 4-byte little-endian integer,
+/// so elements are limited to 4 GiB.
+pub fn encode_bytes_elements<'a, B: AsRef<[u8]> + 'a, I: Iterator<Item = &'a B>>(values: I, output: &mut Vec<u8>) -> Result<(), TritonError> {
+    for value in values {
+        let value = value.as_ref();
+        output.extend_from_slice(&bytes_element_length(value.len())?.to_le_bytes());
+        output.extend_from_slice(value);
+    }
+
+    Ok(())
+}
+
+/// Splits serialized BYTES content into its elements.
+pub fn decode_bytes_elements(bytes: &[u8]) -> Result<Vec<&[u8]>, TritonError> {
+    let mut elements = Vec::new();
+    let mut remaining = bytes;
+
+    while !remaining.is_empty() {
+        if remaining.len() < 4 {
+            return Err(TritonError::DataType(format!("truncated BYTES element length ({} bytes left)", remaining.len())));
+        }
+
+        let length = u32::from_le_bytes(remaining[..4].try_into().unwrap()) as usize;
+        remaining = &remaining[4..];
+
+        if remaining.len() < length {
+            return Err(TritonError::DataType(format!("BYTES element of {} bytes exceeds the {} remaining bytes", length, remaining.len())));
+        }
+
+        elements.push(&remaining[..length]);
+        remaining = &remaining[length..];
+    }
+
+    Ok(elements)
+}
+
+impl TensorElement for Vec<u8> {
+    const DATATYPE: DataType = DataType::Bytes;
+
+    fn encode<'a, I: Iterator<Item = &'a Self>>(values: I, output: &mut Vec<u8>) -> Result<(), TritonError> {
+        encode_bytes_elements(values, output)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Vec<Self>, TritonError> {
+        Ok(decode_bytes_elements(bytes)?.into_iter().map(|element| element.to_vec()).collect())
+    }
+}
+
+impl TensorElement for String {
+    const DATATYPE: DataType = DataType::Bytes;
+
+    fn encode<'a, I: Iterator<Item = &'a Self>>(values: I, output: &mut Vec<u8>) -> Result<(), TritonError> {
+        encode_bytes_elements(values, output)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Vec<Self>, TritonError> {
+        decode_bytes_elements(bytes)?.into_iter()
+            .map(|element| String::from_utf8(element.to_vec()).map_err(|err| TritonError::DataType(format!("BYTES element is not valid UTF-8: {}", err))))
+            .collect()
+    }
+}
+
+pub(crate) fn check_byte_length(byte_length: usize, element_size: usize, datatype: DataType) -> Result<(), TritonError> {
+    if byte_length.checked_rem(element_size) != Some(0) {
+        return Err(TritonError::Shape(format!("{} bytes is not a multiple of the {} element size ({} bytes)", byte_length, datatype, element_size)));
+    }
+
+    Ok(())
+}
+
+pub(crate) fn shape_to_usize(shape: &[i64]) -> Result<Vec<usize>, TritonError> {
+    shape.iter().map(|dim| usize::try_from(*dim).map_err(|_| TritonError::Shape(format!("invalid dimension {} in shape {:?}", dim, shape)))).collect()
+}
+
+// Size in bytes of `shape` elements of `element_size` bytes (1 gives the element count), None on overflow
+pub(crate) fn checked_size(shape: &[usize], element_size: usize) -> Option<usize> {
+    shape.iter().try_fold(element_size, |size, dim| size.checked_mul(*dim))
+}
+
+/// An owned tensor: name, datatype, shape and serialized content.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tensor {
+    name: String,
+    datatype: DataType,
+    shape: Vec<i64>,
+    data: Vec<u8>
+}
+
+impl Tensor {
+    /// Creates a tensor from serialized content, checking that the content matches the shape.
+    pub fn new(name: &str, datatype: DataType, shape: Vec<i64>, data: Vec<u8>) -> Result<Self, TritonError> {
+        let dims = shape_to_usize(&shape)?;
+        let too_large = || TritonError::Shape(format!("tensor '{}' of shape {:?} is too large", name, shape));
+
+        match datatype.size() {
+            Some(element_size) => {
+                let byte_size = checked_size(&dims, element_size).ok_or_else(too_large)?;
+                if byte_size != data.len() {
+                    return Err(TritonError::Shape(format!("tensor '{}' of shape {:?} and datatype {} requires {} bytes, got {}", name, shape, datatype, byte_size, data.len())));
+                }
+            }
+            None => {
+                let element_count = checked_size(&dims, 1).ok_or_else(too_large)?;
+                let bytes_count = decode_bytes_elements(&data)?.len();
+                if element_count != bytes_count {
+                    return Err(TritonError::Shape(format!("tensor '{}' of shape {:?} requires {} BYTES elements, got {}", name, shape, element_count, bytes_count)));
+                }
+            }
+        }
+
+        Ok(Tensor {
+            name: name.to_string(),
+            datatype,
+            shape,
+            data
+        })
+    }
+
+    /// Serializes an ndarray in logical order, whatever its memory layout.
+    ///
+    /// Arrays in standard layout are copied at once, other layouts (transposed,
+    /// sliced or broadcast views) are read element by element.
+    pub fn from_array<T: TensorElement, S: Data<Elem = T>, D: Dimension>(name: &str, array: &ArrayBase<S, D>) -> Result<Self, TritonError> {
+        let mut data = Vec::with_capacity(array.len() * T::DATATYPE.size().unwrap_or(0));
+
+        match array.as_slice() {
+            Some(values) => T::encode_slice(values, &mut data)?,
+            None => T::encode(array.iter(), &mut data)?,
+        }
+
+        Ok(Tensor {
+            name: name.to_string(),
+            datatype: T::DATATYPE,
+            shape: array.shape().iter().map(|dim| *dim as i64).collect(),
+            data
+        })
+    }
+
+    pub fn from_vec<T: TensorElement>(name: &str, shape: Vec<i64>, values: &[T]) -> Result<Self, TritonError> {
+        let element_count = checked_size(&shape_to_usize(&shape)?, 1)
+            .ok_or_else(|| TritonError::Shape(format!("shape {:?} has too many elements", shape)))?;
+        if element_count != values.len() {
+            return Err(TritonError::Shape(format!("shape {:?} requires {} elements, got {}", shape, element_count, values.len())));
+        }
+
+        let mut data = Vec::new();
+        T::encode(values.iter(), &mut data)?;
+
+        Tensor::new(name, T::DATATYPE, shape, data)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn datatype(&self) -> DataType {
+        self.datatype
+    }
+
+    pub fn shape(&self) -> &[i64] {
+        &self.shape
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    fn check_datatype<T: TensorElement>(&self) -> Result<(), TritonError> {
+        if T::DATATYPE != self.datatype {
+            return Err(TritonError::DataType(format!("tensor '{}' has datatype {}, cannot be read as {}", self.name, self.datatype, T::DATATYPE)));
+        }
+
+        Ok(())
+    }
+
+    pub fn to_vec<T: TensorElement>(&self) -> Result<Vec<T>, TritonError> {
+        self.check_datatype::<T>()?;
+
+        T::decode(&self.data)
+    }
+
+    pub fn to_array<T: TensorElement>(&self) -> Result<ArrayD<T>, TritonError> {
+        let shape = shape_to_usize(&self.shape)?;
+        let values = self.to_vec::<T>()?;
+
+        ArrayD::from_shape_vec(IxDyn(&shape), values).map_err(|err| TritonError::Shape(format!("tensor '{}': {}", self.name, err)))
+    }
+
+    /// Returns the input description and the raw content to put in a `ModelInferRequest`.
+    pub fn into_infer_input(self, parameters_map: HashMap<String, InferParameter>) -> (InferInputTensor, Vec<u8>) {
+        let input = InferInputTensor {
+            name: self.name,
+            datatype: self.datatype.as_str().to_string(),
+            shape: self.shape,
+            parameters: parameters_map,
+            contents: None
+        };
+
+        (input, self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_round_trip() {
+        let values = vec!["".to_string(), "hello".to_string(), "".to_string(), "é".to_string()];
+        let mut data = Vec::new();
+        String::encode(values.iter(), &mut data).unwrap();
+
+        assert_eq!(data.len(), 4 * 4 + 5 + 2);
+        assert_eq!(&data[..4], &[0, 0, 0, 0]);
+        assert_eq!(String::decode(&data).unwrap(), values);
+
+        let bytes = vec![vec![], vec![0u8, 255]];
+        let mut data = Vec::new();
+        Vec::<u8>::encode(bytes.iter(), &mut data).unwrap();
+        assert_eq!(Vec::<u8>::decode(&data).unwrap(), bytes);
+    }
+
+    #[test]
+    fn bytes_empty_content() {
+        assert!(decode_bytes_elements(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn bytes_truncated() {
+        let mut data = Vec::new();
+        encode_bytes_elements(["abcdef"].iter(), &mut data).unwrap();
+
+        // Truncated length prefix
+        assert!(matches!(decode_bytes_elements(&data[..2]), Err(TritonError::DataType(_))));
+        // Truncated element
+        assert!(matches!(decode_bytes_elements(&data[..7]), Err(TritonError::DataType(_))));
+        // Trailing partial prefix after a complete element
+        data.extend_from_slice(&[1, 0]);
+        assert!(matches!(decode_bytes_elements(&data), Err(TritonError::DataType(_))));
+    }
+
+    #[test]
+    fn bytes_invalid_utf8() {
+        let mut data = Vec::new();
+        encode_bytes_elements([vec![0xffu8, 0xfe]].iter(), &mut data).unwrap();
+        assert!(String::decode(&data).is_err());
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn bytes_element_too_large() {
+        assert_eq!(bytes_element_length(u32::MAX as usize).unwrap(), u32::MAX);
+        assert!(matches!(bytes_element_length(u32::MAX as usize + 1), Err(TritonError::DataType(_))));
+    }
+
+    #[test]
+    fn shape_overflow() {
+        assert_eq!(checked_size(&[2, 3, 4], 4), Some(96));
+        assert_eq!(checked_size(&[], 8), Some(8));
+        assert_eq!(checked_size(&[usize::MAX, 2], 1), None);
+
+        // 2^62 * 4 elements of 4 bytes wrap around to 0 bytes
+        let shape = vec![1 << 62, 4];
+        assert!(matches!(Tensor::new("x", DataType::Fp32, shape.clone(), Vec::new()), Err(TritonError::Shape(_))));
+        assert!(matches!(Tensor::new("x", DataType::Bytes, shape.clone(), Vec::new()), Err(TritonError::Shape(_))));
+        assert!(matches!(Tensor::from_vec::<f32>("x", shape, &[]), Err(TritonError::Shape(_))));
+
+        assert!(matches!(Tensor::new("x", DataType::Fp32, vec![-1, 4], Vec::new()), Err(TritonError::Shape(_))));
+        assert_eq!(Tensor::new("x", DataType::Fp32, vec![0, 4], Vec::new()).unwrap().data().len(), 0);
+    }
+
+    #[test]
+    fn non_standard_layouts() {
+        let array = ndarray::arr2(&[[1i32, 2, 3, 4], [5, 6, 7, 8]]);
+
+        let transposed = Tensor::from_array("x", &array.t()).unwrap();
+        assert_eq!(transposed, Tensor::from_vec("x", vec![4, 2], &[1i32, 5, 2, 6, 3, 7, 4, 8]).unwrap());
+
+        let sliced = Tensor::from_array("x", &array.slice(ndarray::s![.., ..;2])).unwrap();
+        assert_eq!(sliced, Tensor::from_vec("x", vec![2, 2], &[1i32, 3, 5, 7]).unwrap());
+
+        let row = ndarray::arr1(&[1.5f64, -2.0]);
+        let broadcast = Tensor::from_array("x", &row.broadcast((3, 2)).unwrap()).unwrap();
+        assert_eq!(broadcast, Tensor::from_vec("x", vec![3, 2], &[1.5f64, -2.0, 1.5, -2.0, 1.5, -2.0]).unwrap());
+
+        let strings = ndarray::arr2(&[["a".to_string(), "bc".to_string()], ["".to_string(), "d".to_string()]]);
+        let transposed = Tensor::from_array("x", &strings.t()).unwrap();
+        assert_eq!(transposed.to_vec::<String>().unwrap(), ["a", "", "bc", "d"]);
+        assert_eq!(transposed.to_array::<String>().unwrap(), strings.t().into_dyn());
+    }
+
+    #[test]
+    #[cfg(feature = "half")]
+    fn half_round_trip() {
+        let values = ndarray::arr2(&[[1.0f32, -2.0], [0.5, 0.0]]);
+
+        let tensor = Tensor::from_array("x", &f32_to_f16(&values)).unwrap();
+        assert_eq!(tensor.datatype(), DataType::Fp16);
+        assert_eq!(tensor.data(), [0x00, 0x3c, 0x00, 0xc0, 0x00, 0x38, 0x00, 0x00]);
+        assert_eq!(f16_to_f32(&tensor.to_array::<f16>().unwrap()), values.clone().into_dyn());
+        assert!(matches!(tensor.to_array::<bf16>(), Err(TritonError::DataType(_))));
+
+        let tensor = Tensor::from_array("x", &f32_to_bf16(&values)).unwrap();
+        assert_eq!(tensor.datatype(), DataType::Bf16);
+        assert_eq!(tensor.data(), [0x80, 0x3f, 0x00, 0xc0, 0x00, 0x3f, 0x00, 0x00]);
+        assert_eq!(bf16_to_f32(&tensor.to_array::<bf16>().unwrap()), values.into_dyn());
+        assert!(matches!(tensor.to_array::<f16>(), Err(TritonError::DataType(_))));
+
+        // Non-standard layouts go through the element by element path
+        let transposed = ndarray::arr2(&[[f16::ONE, f16::ZERO], [f16::NEG_ONE, f16::MAX]]);
+        let tensor = Tensor::from_array("x", &transposed.t()).unwrap();
+        assert_eq!(tensor.to_vec::<f16>().unwrap(), [f16::ONE, f16::NEG_ONE, f16::ZERO, f16::MAX]);
+    }
+
+    #[test]
+    #[cfg(feature = "half")]
+    fn half_conversions() {
+        let values = ndarray::arr1(&[1.0f32 / 3.0, 70000.0, 1.0e-8]);
+
+        let fp16 = f32_to_f16(&values);
+        assert_eq!(fp16[0], f16::from_f32(1.0 / 3.0));
+        assert!(fp16[1].is_infinite());
+        assert_eq!(fp16[2], f16::ZERO);
+
+        // BF16 keeps the f32 range with less precision
+        let brain = f32_to_bf16(&values);
+        assert!((brain[1].to_f32() - 70000.0).abs() < 512.0);
+        assert!(brain[2].to_f32() > 0.0);
+
+        for (datatype, name) in [(DataType::Fp16, "FP16"), (DataType::Bf16, "BF16")] {
+            assert_eq!(datatype.as_str(), name);
+            assert_eq!(name.parse::<DataType>().unwrap(), datatype);
+            assert_eq!(datatype.size(), Some(2));
+        }
+        assert_eq!(<f16 as TensorElement>::DATATYPE, DataType::Fp16);
+        assert_eq!(<bf16 as TensorElement>::DATATYPE, DataType::Bf16);
+    }
+}
@@ -2,20 +2,14 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
-use inference::grpc_inference_service_client::GrpcInferenceServiceClient;
-use inference::{ServerLiveRequest, ServerReadyRequest, ModelReadyRequest};
-use inference::{InferParameter, ModelInferRequest, ModelInferResponse, infer_parameter};
-use inference::{ModelMetadataRequest, ModelMetadataResponse};
+use inference::{InferParameter, ModelInferRequest};
+use inference::{ModelConfig, ModelMetadataResponse, ServerMetadataResponse, ModelStatisticsResponse, RepositoryIndexResponse};
+use inference::{TraceSettingResponse, LogSettingsResponse, log_settings_request};
 use inference::model_infer_request::{InferInputTensor, InferRequestedOutputTensor};
-use inference::{CudaSharedMemoryRegisterRequest, CudaSharedMemoryRegisterResponse};
-use inference::{CudaSharedMemoryStatusRequest, CudaSharedMemoryStatusResponse};
-use inference::{CudaSharedMemoryUnregisterResponse, CudaSharedMemoryUnregisterRequest};
-use inference::{SystemSharedMemoryRegisterRequest, SystemSharedMemoryRegisterResponse};
-use inference::{SystemSharedMemoryStatusRequest, SystemSharedMemoryStatusResponse};
-use inference::{SystemSharedMemoryUnregisterRequest, SystemSharedMemoryUnregisterResponse};
-
-use std;
-use std::error::Error;
+#[cfg(feature = "cuda")]
+use inference::{CudaSharedMemoryStatusResponse, CudaSharedMemoryUnregisterResponse};
+use inference::{SystemSharedMemoryStatusResponse, SystemSharedMemoryUnregisterResponse};
+
 use std::vec::Vec;
 use std::collections::HashMap;
 
@@ -49,283 +43,238 @@ same conditions as regards security.
 The fact that you are presently reading this means that you have had
 knowledge of the CeCILL-B license and that you accept its terms.*/
 
-use std::{slice, mem};
+use std::sync::Arc;
+
 use ndarray::{ArrayBase, Data, Dimension};
 
 use tokio::runtime::Runtime;
-use crossbeam_channel::bounded;
+use tonic::metadata::MetadataMap;
+use tonic::service::Interceptor;
 
+pub mod async_client;
+pub mod balanced;
+pub mod builder;
+#[cfg(feature = "cuda")]
 pub mod cuda_shared_memory;
+pub mod error;
+pub mod model;
+pub mod model_config;
+mod registration;
+pub mod repository;
+pub mod request;
+pub mod response;
+pub mod retry;
+pub mod sequence;
+pub mod shared_memory_pool;
+pub mod stream;
 pub mod system_shared_memory;
+pub mod tensor;
+pub mod validation;
+
+pub use async_client::AsyncTritonClient;
+pub use balanced::{BalancedClient, BalancedClientBuilder, BalanceStrategy, BlockingBalancedClient, EndpointStatus};
+pub use builder::ClientBuilder;
+pub use error::TritonError;
+pub use model::Model;
+pub use model_config::{parse_model_config, model_config_to_pbtxt, model_config_to_json, read_model_config, write_model_config};
+pub use repository::ModelLoadOptions;
+pub use request::InferRequest;
+pub use response::InferResponse;
+pub use retry::RetryPolicy;
+pub use sequence::{Sequence, SequenceId};
+pub use shared_memory_pool::{SystemSharedMemoryPool, SystemSharedMemorySlice};
+pub use tensor::{DataType, FixedSizeElement, Tensor, TensorElement};
+pub use validation::{validate_repository, ValidationIssue};
+pub use stream::{InferStreamSender, InferStream, StreamInferResponse, BlockingInferStream};
 
 pub mod inference {
     tonic::include_proto!("inference");
 }
 
 pub struct TritonInference {
-    rt: Runtime,
-    client: GrpcInferenceServiceClient<tonic::transport::Channel>
+    rt: Arc<Runtime>,
+    client: AsyncTritonClient
 }
 
 impl TritonInference {
-    pub fn connect(address: &'static str) -> Result<Self, Box<dyn Error>> {
-
-        let rt  = Runtime::new().unwrap();
-        let (tx, rx) = bounded(1);
-
-        rt.block_on(async {
-            let resp = GrpcInferenceServiceClient::connect(address).await;
-            tx.send(resp).unwrap();
-        });
-        let client = rx.recv().unwrap().unwrap();
-
-        Ok(TritonInference {
-            rt: rt,
-            client: client,
-        })
+    pub fn connect<A: Into<String>>(address: A) -> Result<Self, TritonError> {
+        ClientBuilder::new(address).connect_blocking()
     }
 
-    pub fn is_server_live(&mut self) -> Result<bool,  Box<dyn Error>> {
-        let request = tonic::Request::new(ServerLiveRequest {});
-
-        let (tx, rx) = bounded(1);
-        self.rt.block_on(async {
-            let resp = self.client.server_live(request).await;
-            tx.send(resp).unwrap();
-        });
-
-        let response = rx.recv().unwrap().unwrap();
-
-        Ok(response.get_ref().live)
+    pub(crate) fn from_parts(rt: Runtime, client: AsyncTritonClient) -> Self {
+        TritonInference {
+            rt: Arc::new(rt),
+            client,
+        }
     }
 
-    pub fn is_server_ready(&mut self) -> Result<bool,  Box<dyn Error>> {
-        let request = tonic::Request::new(ServerReadyRequest {});
+    /// Returns a client sending `metadata` with every request, sharing the runtime and
+    /// the channel of `self`.
+    pub fn with_metadata(&self, metadata: &MetadataMap) -> TritonInference {
+        TritonInference { rt: self.rt.clone(), client: self.client.with_metadata(metadata) }
+    }
 
-        let (tx, rx) = bounded(1);
-        self.rt.block_on(async {
-            let resp = self.client.server_ready(request).await;
-            tx.send(resp).unwrap();
-        });
+    /// Returns a client sending the header `key: value` with every request.
+    pub fn with_header(&self, key: &str, value: &str) -> Result<TritonInference, TritonError> {
+        Ok(TritonInference { rt: self.rt.clone(), client: self.client.with_header(key, value)? })
+    }
 
-        let response = rx.recv().unwrap().unwrap();
+    pub fn with_interceptor<I: Interceptor + Send + 'static>(&self, interceptor: I) -> TritonInference {
+        TritonInference { rt: self.rt.clone(), client: self.client.with_interceptor(interceptor) }
+    }
 
-        Ok(response.get_ref().ready)
+    /// Returns a client retrying failed calls according to `policy`.
+    pub fn with_retry_policy(&self, policy: RetryPolicy) -> TritonInference {
+        TritonInference { rt: self.rt.clone(), client: self.client.with_retry_policy(policy) }
     }
 
-    pub fn is_model_ready(&mut self, model_name: &str, version_number: &str) -> Result<bool,  Box<dyn Error>> {
-        let request = tonic::Request::new(ModelReadyRequest {name: model_name.to_string(), version: version_number.to_string()});
+    pub fn async_client(&self) -> AsyncTritonClient {
+        self.client.clone()
+    }
 
-        let (tx, rx) = bounded(1);
-        self.rt.block_on(async {
-            let resp = self.client.model_ready(request).await;
-            tx.send(resp).unwrap();
-        });
+    pub fn is_server_live(&mut self) -> Result<bool,  TritonError> {
+        self.rt.block_on(self.client.is_server_live())
+    }
 
-        let response = rx.recv().unwrap().unwrap();
+    pub fn is_server_ready(&mut self) -> Result<bool,  TritonError> {
+        self.rt.block_on(self.client.is_server_ready())
+    }
 
-        Ok(response.get_ref().ready)
+    pub fn is_model_ready(&mut self, model_name: &str, version_number: &str) -> Result<bool,  TritonError> {
+        self.rt.block_on(self.client.is_model_ready(model_name, version_number))
     }
 
-    pub fn get_infer_input(&mut self, input_name: &str, input_datatype: &str, tensor_shape: &[i64], parameters_map: HashMap<String, InferParameter>) -> InferInputTensor {
+    pub fn get_server_metadata(&mut self) -> Result<ServerMetadataResponse,  TritonError> {
+        self.rt.block_on(self.client.get_server_metadata())
+    }
 
-        InferInputTensor {
-            name: input_name.to_string(),
-            datatype: input_datatype.to_string(),
-            shape: tensor_shape.to_vec(),
-            parameters: parameters_map,
-            contents: None
-        }
+    pub fn get_infer_input(&mut self, input_name: &str, input_datatype: DataType, tensor_shape: &[i64], parameters_map: HashMap<String, InferParameter>) -> InferInputTensor {
+        self.client.get_infer_input(input_name, input_datatype, tensor_shape, parameters_map)
     }
 
     pub fn get_infer_output(&mut self, input_name: &str, parameters_map: HashMap<String, InferParameter>) -> InferRequestedOutputTensor {
-
-        InferRequestedOutputTensor {
-            name: input_name.to_string(),
-            parameters: parameters_map
-        }
+        self.client.get_infer_output(input_name, parameters_map)
     }
 
-    pub fn get_model_metadata(&mut self, model_name: &str, model_version: &str) -> Result<ModelMetadataResponse,  Box<dyn Error>> {
-        let request = tonic::Request::new(ModelMetadataRequest {name: model_name.to_string(), version: model_version.to_string()});
-
-        let (tx, rx) = bounded(1);
-        self.rt.block_on(async {
-            let resp = self.client.model_metadata(request).await;
-            tx.send(resp).unwrap();
-        });
-
-        let response = rx.recv().unwrap().unwrap();
-
-        Ok(response.get_ref().clone())
+    pub fn get_model_metadata(&mut self, model_name: &str, model_version: &str) -> Result<ModelMetadataResponse,  TritonError> {
+        self.rt.block_on(self.client.get_model_metadata(model_name, model_version))
     }
 
-    pub fn infer(&mut self, model_name: &str, model_version: &str, request_id: &str, inputs_vec: Vec<InferInputTensor>, outputs_vec: Vec<InferRequestedOutputTensor>, input_content: Vec<Vec<u8>>) -> Result<ModelInferResponse,  Box<dyn Error>> {
-
-        let request = tonic::Request::new(
-            ModelInferRequest {
-                model_name: model_name.to_string(),
-                model_version: model_version.to_string(),
-                id: request_id.to_string(),
-                parameters: HashMap::<String, InferParameter>::new(),
-                inputs: inputs_vec,
-                outputs: outputs_vec,
-                raw_input_contents: input_content
-
-            }
-        );
-
-        let (tx, rx) = bounded(1);
-        self.rt.block_on(async {
-            let resp = self.client.model_infer(request).await;
-            tx.send(resp).unwrap();
-        });
-
-        let response = rx.recv().unwrap().unwrap();
-
-        Ok(response.get_ref().clone())
+    pub fn get_model_config(&mut self, model_name: &str, model_version: &str) -> Result<ModelConfig,  TritonError> {
+        self.rt.block_on(self.client.get_model_config(model_name, model_version))
     }
 
-    pub fn get_input_content_from_ndarray<T: Data, D: Dimension>(&mut self, input_array: &ArrayBase<T, D>) -> Vec<u8> {
-
-        let bytes_length = input_array.shape().iter().product::<usize>() * mem::size_of::<T::Elem>();
-        let tensor_bytes = unsafe { slice::from_raw_parts(input_array.as_ptr() as *const u8, bytes_length).to_vec() };
-
-        tensor_bytes
+    pub fn get_model_statistics(&mut self, model_name: &str, model_version: &str) -> Result<ModelStatisticsResponse,  TritonError> {
+        self.rt.block_on(self.client.get_model_statistics(model_name, model_version))
     }
 
-    pub fn create_cuda_shared_memory(&mut self, name: &'static str, size: usize, device_id: i64) -> Result<cuda_shared_memory::CudaSharedMemoryRegionHandle,  Box<dyn Error>> {
-
-        let mut cuda_handle = cuda_shared_memory::CudaSharedMemoryRegionHandle::create(name, size, device_id);
-        let cuda_raw_handle = cuda_handle.get_raw_handle();
-
-        let request = tonic::Request::new(
-            CudaSharedMemoryRegisterRequest {
-                name: name.to_string(),
-                raw_handle: cuda_raw_handle,
-                device_id: device_id,
-                byte_size: (size as u64)
-            }
-        );
-
-        let (tx, rx) = bounded(1);
-        self.rt.block_on(async {
-            let resp = self.client.cuda_shared_memory_register(request).await;
-            tx.send(resp).unwrap();
-        });
-
-        let _response = rx.recv().unwrap().unwrap();
-
-        Ok(cuda_handle)
+    pub fn get_repository_index(&mut self, repository_name: &str, ready: bool) -> Result<RepositoryIndexResponse,  TritonError> {
+        self.rt.block_on(self.client.get_repository_index(repository_name, ready))
     }
 
-    pub fn cuda_shared_memory_status(&mut self, name: &'static str) -> Result<CudaSharedMemoryStatusResponse,  Box<dyn Error>> {
-
-        let request = tonic::Request::new(
-            CudaSharedMemoryStatusRequest {
-                name: name.to_string()
-            }
-        );
-
-        let (tx, rx) = bounded(1);
-        self.rt.block_on(async {
-            let resp = self.client.cuda_shared_memory_status(request).await;
-            tx.send(resp).unwrap();
-        });
-
-        let response = rx.recv().unwrap().unwrap();
-
-        Ok(response.get_ref().clone())
+    pub fn load_model(&mut self, model_name: &str, options: ModelLoadOptions) -> Result<(),  TritonError> {
+        self.rt.block_on(self.client.load_model(model_name, options))
     }
 
-    pub fn unregister_cuda_shared_memory(&mut self, name: &'static str) -> Result<CudaSharedMemoryUnregisterResponse,  Box<dyn Error>> {
+    pub fn unload_model(&mut self, model_name: &str, unload_dependents: bool) -> Result<(),  TritonError> {
+        self.rt.block_on(self.client.unload_model(model_name, unload_dependents))
+    }
 
-        let request = tonic::Request::new(
-            CudaSharedMemoryUnregisterRequest {
-                name: name.to_string()
-            }
-        );
+    pub fn get_trace_settings(&mut self, model_name: &str) -> Result<TraceSettingResponse,  TritonError> {
+        self.rt.block_on(self.client.get_trace_settings(model_name))
+    }
 
-        let (tx, rx) = bounded(1);
-        self.rt.block_on(async {
-            let resp = self.client.cuda_shared_memory_unregister(request).await;
-            tx.send(resp).unwrap();
-        });
+    pub fn update_trace_settings(&mut self, model_name: &str, settings: HashMap<String, Vec<String>>) -> Result<TraceSettingResponse,  TritonError> {
+        self.rt.block_on(self.client.update_trace_settings(model_name, settings))
+    }
 
-        let response = rx.recv().unwrap().unwrap();
+    pub fn get_log_settings(&mut self) -> Result<LogSettingsResponse,  TritonError> {
+        self.rt.block_on(self.client.get_log_settings())
+    }
 
-        Ok(response.get_ref().clone())
+    pub fn update_log_settings(&mut self, settings: HashMap<String, log_settings_request::SettingValue>) -> Result<LogSettingsResponse,  TritonError> {
+        self.rt.block_on(self.client.update_log_settings(settings))
     }
 
-    pub fn create_system_shared_memory(&mut self, name: &'static str, key: &'static str, size: usize) -> Result<system_shared_memory::SystemSharedMemoryRegionHandle,  Box<dyn Error>> {
+    pub fn get_model(&mut self, model_name: &str, model_version: &str) -> Result<Model,  TritonError> {
+        self.rt.block_on(self.client.get_model(model_name, model_version))
+    }
 
-        let shm_handle = system_shared_memory::SystemSharedMemoryRegionHandle::create(name, key, size);
+    /// Checks a request against the model metadata and sends it.
+    pub fn infer_model(&mut self, model: &Model, request: InferRequest) -> Result<InferResponse,  TritonError> {
+        self.rt.block_on(model.infer(request))
+    }
 
-        let request = tonic::Request::new(
-            SystemSharedMemoryRegisterRequest {
-                name: name.to_string(),
-                key: key.to_string(),
-                offset: 0,
-                byte_size: (size as u64)
-            }
-        );
+    pub fn infer(&mut self, request: InferRequest) -> Result<InferResponse,  TritonError> {
+        self.rt.block_on(self.client.infer(request))
+    }
 
-        let (tx, rx) = bounded(1);
-        self.rt.block_on(async {
-            let resp = self.client.system_shared_memory_register(request).await;
-            tx.send(resp).unwrap();
-        });
+    pub fn model_infer(&mut self, request: ModelInferRequest) -> Result<InferResponse,  TritonError> {
+        self.rt.block_on(self.client.model_infer(request))
+    }
 
-        let _response = rx.recv().unwrap().unwrap();
+    pub fn infer_sequence(&mut self, sequence: &mut Sequence, request: InferRequest, last: bool) -> Result<InferResponse,  TritonError> {
+        self.rt.block_on(self.client.infer_sequence(sequence, request, last))
+    }
 
-        Ok(shm_handle)
+    pub fn stream_infer(&mut self) -> BlockingInferStream<'_> {
+        let (sender, stream) = self.client.stream_infer();
+        BlockingInferStream::new(&self.rt, sender, stream)
     }
 
-    pub fn system_shared_memory_status(&mut self, name: &'static str) -> Result<SystemSharedMemoryStatusResponse,  Box<dyn Error>> {
+    pub fn get_input_content_from_ndarray<T: TensorElement, S: Data<Elem = T>, D: Dimension>(&mut self, input_array: &ArrayBase<S, D>) -> Result<Vec<u8>, TritonError> {
+        self.client.get_input_content_from_ndarray(input_array)
+    }
 
-        let request = tonic::Request::new(
-            SystemSharedMemoryStatusRequest {
-                name: name.to_string()
-            }
-        );
+    #[cfg(feature = "cuda")]
+    pub fn create_cuda_shared_memory(&mut self, name: &str, size: usize, device_id: i64) -> Result<cuda_shared_memory::CudaSharedMemoryRegionHandle,  TritonError> {
+        let mut handle = self.rt.block_on(self.client.create_cuda_shared_memory(name, size, device_id))?;
+        if let Some(registration) = handle.registration_mut() {
+            registration.keep_runtime(self.rt.clone());
+        }
 
-        let (tx, rx) = bounded(1);
-        self.rt.block_on(async {
-            let resp = self.client.system_shared_memory_status(request).await;
-            tx.send(resp).unwrap();
-        });
+        Ok(handle)
+    }
 
-        let response = rx.recv().unwrap().unwrap();
+    #[cfg(feature = "cuda")]
+    pub fn cuda_shared_memory_status(&mut self, name: &str) -> Result<CudaSharedMemoryStatusResponse,  TritonError> {
+        self.rt.block_on(self.client.cuda_shared_memory_status(name))
+    }
 
-        Ok(response.get_ref().clone())
+    #[cfg(feature = "cuda")]
+    pub fn unregister_cuda_shared_memory(&mut self, name: &str) -> Result<CudaSharedMemoryUnregisterResponse,  TritonError> {
+        self.rt.block_on(self.client.unregister_cuda_shared_memory(name))
     }
 
-    pub fn unregister_system_shared_memory(&mut self, name: &'static str) -> Result<SystemSharedMemoryUnregisterResponse,  Box<dyn Error>> {
+    pub fn create_system_shared_memory(&mut self, name: &str, key: &str, size: usize) -> Result<system_shared_memory::SystemSharedMemoryRegionHandle,  TritonError> {
+        let mut handle = self.rt.block_on(self.client.create_system_shared_memory(name, key, size))?;
+        if let Some(registration) = handle.registration_mut() {
+            registration.keep_runtime(self.rt.clone());
+        }
 
-        let request = tonic::Request::new(
-            SystemSharedMemoryUnregisterRequest {
-                name: name.to_string()
-            }
-        );
+        Ok(handle)
+    }
 
-        let (tx, rx) = bounded(1);
-        self.rt.block_on(async {
-            let resp = self.client.system_shared_memory_unregister(request).await;
-            tx.send(resp).unwrap();
-        });
+    /// Blocking version of `AsyncTritonClient::create_system_shared_memory_pool`.
+    pub fn create_system_shared_memory_pool(&mut self, name_prefix: &str, region_count: usize, region_size: usize) -> Result<SystemSharedMemoryPool, TritonError> {
+        let mut regions = Vec::with_capacity(region_count);
+        for index in 0..region_count {
+            let name = format!("{}_{}", name_prefix, index);
+            regions.push(self.create_system_shared_memory(&name, &format!("/{}", name), region_size)?);
+        }
 
-        let response = rx.recv().unwrap().unwrap();
+        Ok(SystemSharedMemoryPool::from_regions(regions))
+    }
 
-        Ok(response.get_ref().clone())
+    pub fn system_shared_memory_status(&mut self, name: &str) -> Result<SystemSharedMemoryStatusResponse,  TritonError> {
+        self.rt.block_on(self.client.system_shared_memory_status(name))
     }
 
-    pub fn get_system_shared_memory_params(&mut self, name: &'static str, size: u64, offset: u64) -> HashMap<String, InferParameter> {
-        let params = HashMap::from([
-            ("shared_memory_region".to_string(), InferParameter { parameter_choice: Some(infer_parameter::ParameterChoice::StringParam(name.to_string())) }),
-            ("shared_memory_byte_size".to_string(), InferParameter { parameter_choice: Some(infer_parameter::ParameterChoice::Int64Param(size.try_into().unwrap())) }),
-            ("shared_memory_offset".to_string(), InferParameter { parameter_choice: Some(infer_parameter::ParameterChoice::Int64Param(offset.try_into().unwrap())) })
-        ]);
+    pub fn unregister_system_shared_memory(&mut self, name: &str) -> Result<SystemSharedMemoryUnregisterResponse,  TritonError> {
+        self.rt.block_on(self.client.unregister_system_shared_memory(name))
+    }
 
-        params
+    pub fn get_system_shared_memory_params(&mut self, name: &str, size: u64, offset: u64) -> Result<HashMap<String, InferParameter>, TritonError> {
+        self.client.get_system_shared_memory_params(name, size, offset)
     }
 }
@@ -0,0 +1,526 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::TritonError;
+use crate::inference::{DataType as ConfigDataType, ModelConfig};
+use crate::inference::model_config::SchedulingChoice;
+use crate::inference::model_version_policy::PolicyChoice;
+use crate::model_config::read_model_config;
+
+/// A problem found in a model repository by `validate_repository`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    /// Name of the model directory, empty for problems of the repository itself.
+    pub model: String,
+    pub path: PathBuf,
+    pub message: String
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+// Default model file names of the backends shipped with Triton, a directory
+// is also accepted (e.g. ONNX models with external weights)
+const BACKEND_FILES: &[(&str, &str, &str)] = &[
+    ("onnxruntime_onnx", "onnxruntime", "model.onnx"),
+    ("tensorrt_plan", "tensorrt", "model.plan"),
+    ("pytorch_libtorch", "pytorch", "model.pt"),
+    ("tensorflow_savedmodel", "tensorflow", "model.savedmodel"),
+    ("tensorflow_graphdef", "tensorflow", "model.graphdef"),
+    ("", "python", "model.py"),
+    ("", "openvino", "model.xml"),
+];
+
+const ENSEMBLE_PLATFORM: &str = "ensemble";
+
+struct ModelEntry {
+    name: String,
+    directory: PathBuf,
+    config: Option<ModelConfig>
+}
+
+struct Report {
+    issues: Vec<ValidationIssue>
+}
+
+impl Report {
+    fn add(&mut self, model: &str, path: &Path, message: String) {
+        self.issues.push(ValidationIssue { model: model.to_string(), path: path.to_path_buf(), message });
+    }
+}
+
+/// Checks a model repository without a server: every model directory must contain a
+/// valid `config.pbtxt` and numeric version subdirectories holding the file expected by
+/// its backend, inputs and outputs must have valid datatypes and dims, and ensemble
+/// steps must reference existing models and tensors.
+///
+/// Returns the list of issues, empty if the repository is valid. An error is only
+/// returned if the repository directory cannot be read.
+pub fn validate_repository<P: AsRef<Path>>(repository: P) -> Result<Vec<ValidationIssue>, TritonError> {
+    let repository = repository.as_ref();
+    let mut report = Report { issues: Vec::new() };
+    let mut models = Vec::new();
+
+    let mut entries = fs::read_dir(repository)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+
+        if !entry.path().is_dir() {
+            report.add("", &entry.path(), "unexpected file at the root of the repository, models must be directories".to_string());
+            continue;
+        }
+
+        let config_path = entry.path().join("config.pbtxt");
+        let config = if config_path.is_file() {
+            match read_model_config(&config_path) {
+                Ok(config) => Some(config),
+                Err(err) => {
+                    report.add(&name, &config_path, err.to_string());
+                    None
+                }
+            }
+        } else {
+            report.add(&name, &entry.path(), "missing config.pbtxt".to_string());
+            None
+        };
+
+        models.push(ModelEntry { name, directory: entry.path(), config });
+    }
+
+    for model in &models {
+        validate_model(model, &models, &mut report);
+    }
+
+    Ok(report.issues)
+}
+
+fn validate_model(model: &ModelEntry, models: &[ModelEntry], report: &mut Report) {
+    let versions = validate_versions(model, report);
+
+    let config = match &model.config {
+        Some(config) => config,
+        None => return,
+    };
+    let config_path = model.directory.join("config.pbtxt");
+
+    if !config.name.is_empty() && config.name != model.name {
+        report.add(&model.name, &config_path, format!("model name '{}' does not match the directory name", config.name));
+    }
+
+    if config.max_batch_size < 0 {
+        report.add(&model.name, &config_path, format!("max_batch_size must be >= 0, got {}", config.max_batch_size));
+    }
+
+    if let Some(PolicyChoice::Specific(specific)) = config.version_policy.as_ref().and_then(|policy| policy.policy_choice.as_ref()) {
+        for version in &specific.versions {
+            if !versions.contains(version) {
+                report.add(&model.name, &config_path, format!("version {} required by the version policy has no directory", version));
+            }
+        }
+    }
+
+    validate_backend(model, config, &versions, report);
+
+    let mut names = HashSet::new();
+    for input in &config.input {
+        validate_tensor(model, "input", &input.name, input.data_type, &input.dims, input.reshape.is_some(), &mut names, report);
+    }
+    let mut names = HashSet::new();
+    for output in &config.output {
+        validate_tensor(model, "output", &output.name, output.data_type, &output.dims, output.reshape.is_some(), &mut names, report);
+    }
+
+    if config.platform == ENSEMBLE_PLATFORM {
+        validate_ensemble(model, config, models, report);
+    } else if let Some(SchedulingChoice::EnsembleScheduling(_)) = config.scheduling_choice {
+        report.add(&model.name, &config_path, format!("ensemble_scheduling requires platform \"{}\"", ENSEMBLE_PLATFORM));
+    }
+}
+
+fn validate_versions(model: &ModelEntry, report: &mut Report) -> Vec<i64> {
+    let mut versions = Vec::new();
+
+    let entries = match fs::read_dir(&model.directory).and_then(|entries| entries.collect::<Result<Vec<_>, _>>()) {
+        Ok(entries) => entries,
+        Err(err) => {
+            report.add(&model.name, &model.directory, format!("cannot read the model directory: {}", err));
+            return versions;
+        }
+    };
+
+    for entry in entries {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+
+        if !entry.path().is_dir() || file_name.starts_with('.') {
+            continue;
+        }
+
+        match file_name.parse::<i64>() {
+            Ok(version) if version >= 0 && !file_name.starts_with('+') => versions.push(version),
+            _ => report.add(&model.name, &entry.path(), "version directories must be named by a non-negative integer".to_string()),
+        }
+    }
+
+    if versions.is_empty() {
+        report.add(&model.name, &model.directory, "no version directory".to_string());
+    }
+    versions.sort_unstable();
+
+    versions
+}
+
+fn validate_backend(model: &ModelEntry, config: &ModelConfig, versions: &[i64], report: &mut Report) {
+    let config_path = model.directory.join("config.pbtxt");
+
+    if config.platform == ENSEMBLE_PLATFORM {
+        if !config.backend.is_empty() {
+            report.add(&model.name, &config_path, format!("ensemble models cannot set a backend, got \"{}\"", config.backend));
+        }
+        return;
+    }
+
+    let known = if !config.platform.is_empty() {
+        match BACKEND_FILES.iter().find(|(platform, _, _)| !platform.is_empty() && *platform == config.platform) {
+            Some(known) => {
+                if !config.backend.is_empty() && config.backend != known.1 {
+                    report.add(&model.name, &config_path, format!("platform \"{}\" requires backend \"{}\", got \"{}\"", config.platform, known.1, config.backend));
+                }
+                Some(known)
+            }
+            None => {
+                report.add(&model.name, &config_path, format!("unknown platform \"{}\"", config.platform));
+                return;
+            }
+        }
+    } else if !config.backend.is_empty() {
+        // Tensorflow needs the platform to pick the model format, custom backends are not checked
+        let candidates: Vec<_> = BACKEND_FILES.iter().filter(|(_, backend, _)| *backend == config.backend).collect();
+        if candidates.len() == 1 { Some(candidates[0]) } else { None }
+    } else {
+        report.add(&model.name, &config_path, "neither platform nor backend is specified".to_string());
+        return;
+    };
+
+    let expected_file = match (known, config.default_model_filename.is_empty()) {
+        (_, false) => config.default_model_filename.as_str(),
+        (Some((_, _, file)), true) => file,
+        (None, true) => return,
+    };
+
+    for version in versions {
+        let version_directory = model.directory.join(version.to_string());
+        if !version_directory.join(expected_file).exists() {
+            report.add(&model.name, &version_directory, format!("missing {} expected by backend \"{}\"", expected_file, known.map(|known| known.1).unwrap_or(&config.backend)));
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn validate_tensor(model: &ModelEntry, kind: &str, name: &str, data_type: i32, dims: &[i64], reshaped: bool, names: &mut HashSet<String>, report: &mut Report) {
+    let config_path = model.directory.join("config.pbtxt");
+
+    if name.is_empty() {
+        report.add(&model.name, &config_path, format!("an {} has no name", kind));
+    } else if !names.insert(name.to_string()) {
+        report.add(&model.name, &config_path, format!("{} '{}' is declared multiple times", kind, name));
+    }
+
+    match ConfigDataType::from_i32(data_type) {
+        None | Some(ConfigDataType::TypeInvalid) => report.add(&model.name, &config_path, format!("{} '{}' has an invalid data_type", kind, name)),
+        Some(_) => (),
+    }
+
+    if dims.is_empty() && !reshaped {
+        report.add(&model.name, &config_path, format!("{} '{}' has no dims", kind, name));
+    }
+    if dims.iter().any(|dim| *dim < -1 || *dim == 0) {
+        report.add(&model.name, &config_path, format!("{} '{}' has invalid dims {:?}, dimensions must be -1 or positive", kind, name, dims));
+    }
+}
+
+fn validate_ensemble(model: &ModelEntry, config: &ModelConfig, models: &[ModelEntry], report: &mut Report) {
+    let config_path = model.directory.join("config.pbtxt");
+
+    let ensemble = match &config.scheduling_choice {
+        Some(SchedulingChoice::EnsembleScheduling(ensemble)) if !ensemble.step.is_empty() => ensemble,
+        _ => {
+            report.add(&model.name, &config_path, "ensemble models require ensemble_scheduling steps".to_string());
+            return;
+        }
+    };
+
+    // Tensors of the ensemble are its inputs and the outputs mapped by the steps
+    let mut available: HashSet<&str> = config.input.iter().map(|input| input.name.as_str()).collect();
+    let mut producers: HashMap<&str, usize> = HashMap::new();
+    for (index, step) in ensemble.step.iter().enumerate() {
+        for tensor in step.output_map.values() {
+            if available.contains(tensor.as_str()) {
+                report.add(&model.name, &config_path, format!("step {} ('{}') writes tensor '{}' which is already produced", index, step.model_name, tensor));
+            }
+            available.insert(tensor);
+            producers.insert(tensor, index);
+        }
+    }
+
+    for (index, step) in ensemble.step.iter().enumerate() {
+        let step_config = match models.iter().find(|entry| entry.name == step.model_name) {
+            Some(entry) => entry.config.as_ref(),
+            None => {
+                report.add(&model.name, &config_path, format!("step {} references model '{}' which is not in the repository", index, step.model_name));
+                None
+            }
+        };
+
+        for (step_input, tensor) in &step.input_map {
+            if !available.contains(tensor.as_str()) {
+                report.add(&model.name, &config_path, format!("step {} ('{}') reads tensor '{}' which is neither an ensemble input nor a step output", index, step.model_name, tensor));
+            }
+            if let Some(step_config) = step_config {
+                if !step_config.input.iter().any(|input| input.name == *step_input) {
+                    report.add(&model.name, &config_path, format!("step {} maps unknown input '{}' of model '{}'", index, step_input, step.model_name));
+                }
+            }
+        }
+
+        if let Some(step_config) = step_config {
+            for step_output in step.output_map.keys() {
+                if !step_config.output.iter().any(|output| output.name == *step_output) {
+                    report.add(&model.name, &config_path, format!("step {} maps unknown output '{}' of model '{}'", index, step_output, step.model_name));
+                }
+            }
+        }
+    }
+
+    for output in &config.output {
+        if !producers.contains_key(output.name.as_str()) {
+            report.add(&model.name, &config_path, format!("ensemble output '{}' is not produced by any step", output.name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const ONNX_CONFIG: &str = r#"
+        platform: "onnxruntime_onnx"
+        max_batch_size: 8
+        input { name: "x" data_type: TYPE_FP32 dims: [ -1, 3 ] }
+        output { name: "y" data_type: TYPE_FP32 dims: [ 10 ] }
+    "#;
+
+    // Repository in a temporary directory, removed on drop
+    struct Repository {
+        path: PathBuf
+    }
+
+    impl Repository {
+        fn new() -> Self {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let path = env::temp_dir().join(format!("triton-rust-validation-{}-{}", process::id(), COUNTER.fetch_add(1, Ordering::Relaxed)));
+            fs::create_dir_all(&path).unwrap();
+
+            Repository { path }
+        }
+
+        fn model(&self, name: &str, config: Option<&str>, files: &[&str]) -> &Self {
+            let directory = self.path.join(name);
+            fs::create_dir_all(&directory).unwrap();
+            if let Some(config) = config {
+                fs::write(directory.join("config.pbtxt"), config).unwrap();
+            }
+            for file in files {
+                let path = directory.join(file);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, b"").unwrap();
+            }
+            self
+        }
+
+        fn messages(&self) -> Vec<String> {
+            validate_repository(&self.path).unwrap().into_iter().map(|issue| format!("{}: {}", issue.model, issue.message)).collect()
+        }
+    }
+
+    impl Drop for Repository {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+
+    fn assert_issue(messages: &[String], expected: &str) {
+        assert!(messages.iter().any(|message| message.contains(expected)), "no issue containing {:?} in {:#?}", expected, messages);
+    }
+
+    #[test]
+    fn valid_repository() {
+        let repository = Repository::new();
+        repository.model("model", Some(ONNX_CONFIG), &["1/model.onnx", "2/model.onnx"]);
+
+        assert_eq!(repository.messages(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn missing_repository() {
+        assert!(validate_repository(env::temp_dir().join("triton-rust-validation-missing")).is_err());
+    }
+
+    #[test]
+    fn missing_config() {
+        let repository = Repository::new();
+        repository.model("model", None, &["1/model.onnx"]);
+
+        assert_eq!(repository.messages(), ["model: missing config.pbtxt"]);
+    }
+
+    #[test]
+    fn invalid_config() {
+        let repository = Repository::new();
+        repository.model("model", Some("platform: \"onnxruntime_onnx\" bogus: 1"), &["1/model.onnx"]);
+
+        assert_issue(&repository.messages(), "no field named 'bogus'");
+    }
+
+    #[test]
+    fn missing_backend_file() {
+        let repository = Repository::new();
+        repository.model("model", Some(ONNX_CONFIG), &["1/model.onnx", "2/weights.bin"]);
+        repository.model("python", Some("backend: \"python\" input { name: \"x\" data_type: TYPE_FP32 dims: [ 1 ] }"), &["1/other.py"]);
+
+        let messages = repository.messages();
+        assert_eq!(messages.len(), 2, "{:#?}", messages);
+        assert_issue(&messages, "model: missing model.onnx expected by backend \"onnxruntime\"");
+        assert_issue(&messages, "python: missing model.py expected by backend \"python\"");
+    }
+
+    #[test]
+    fn bad_version_directories() {
+        let repository = Repository::new();
+        repository.model("model", Some(ONNX_CONFIG), &["latest/model.onnx", "-1/model.onnx", "+2/model.onnx"]);
+        repository.model("empty", Some(ONNX_CONFIG), &[]);
+
+        let messages = repository.messages();
+        assert_eq!(messages.iter().filter(|message| message.contains("non-negative integer")).count(), 3, "{:#?}", messages);
+        assert_issue(&messages, "model: no version directory");
+        assert_issue(&messages, "empty: no version directory");
+    }
+
+    #[test]
+    fn version_policy() {
+        let repository = Repository::new();
+        repository.model("model", Some(&format!("{} version_policy {{ specific {{ versions: [ 1, 3 ] }} }}", ONNX_CONFIG)), &["1/model.onnx"]);
+
+        assert_eq!(repository.messages(), ["model: version 3 required by the version policy has no directory"]);
+    }
+
+    #[test]
+    fn bad_dims() {
+        let repository = Repository::new();
+        repository.model("model", Some(r#"
+            platform: "onnxruntime_onnx"
+            max_batch_size: -1
+            input { name: "zero" data_type: TYPE_FP32 dims: [ 0, 3 ] }
+            input { name: "negative" data_type: TYPE_FP32 dims: [ -2 ] }
+            input { name: "none" data_type: TYPE_FP32 }
+            output { name: "y" dims: [ 1 ] }
+        "#), &["1/model.onnx"]);
+
+        let messages = repository.messages();
+        assert_eq!(messages.len(), 5, "{:#?}", messages);
+        assert_issue(&messages, "max_batch_size must be >= 0, got -1");
+        assert_issue(&messages, "input 'zero' has invalid dims [0, 3]");
+        assert_issue(&messages, "input 'negative' has invalid dims [-2]");
+        assert_issue(&messages, "input 'none' has no dims");
+        assert_issue(&messages, "output 'y' has an invalid data_type");
+    }
+
+    #[test]
+    fn broken_ensemble_step() {
+        let repository = Repository::new();
+        repository.model("model", Some(ONNX_CONFIG), &["1/model.onnx"]);
+        repository.model("pipeline", Some(r#"
+            platform: "ensemble"
+            input { name: "in" data_type: TYPE_FP32 dims: [ 3 ] }
+            output { name: "out" data_type: TYPE_FP32 dims: [ 10 ] }
+            output { name: "unused" data_type: TYPE_FP32 dims: [ 10 ] }
+            ensemble_scheduling {
+              step { model_name: "model" input_map { key: "x" value: "in" } output_map { key: "z" value: "out" } }
+              step { model_name: "absent" input_map { key: "x" value: "missing" } }
+            }
+        "#), &["1/.keep"]);
+
+        let messages = repository.messages();
+        assert_eq!(messages.len(), 4, "{:#?}", messages);
+        assert_issue(&messages, "pipeline: step 0 maps unknown output 'z' of model 'model'");
+        assert_issue(&messages, "pipeline: step 1 references model 'absent' which is not in the repository");
+        assert_issue(&messages, "pipeline: step 1 ('absent') reads tensor 'missing'");
+        assert_issue(&messages, "pipeline: ensemble output 'unused' is not produced by any step");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_model_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let repository = Repository::new();
+        repository.model("readable", Some(ONNX_CONFIG), &["1/model.onnx"]);
+        repository.model("locked", None, &[]);
+        let locked = repository.path.join("locked");
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+        // Permissions are not enforced for root
+        let enforced = fs::read_dir(&locked).is_err();
+        let messages = repository.messages();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+
+        if enforced {
+            assert_issue(&messages, "locked: cannot read the model directory");
+            assert!(!messages.iter().any(|message| message.starts_with("readable")), "{:#?}", messages);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+/* Copyright CATIE, 2022-2023
+
+b.albar@catie.fr
+
+This software is governed by the CeCILL-B license under French law and
+abiding by the rules of distribution of free software.  You can  use,
+modify and/ or redistribute the software under the terms of the CeCILL-B
+license as circulated by CEA, CNRS and INRIA at the following URL
+"http://www.cecill.info".
+
+As a counterpart to the access to the source code and  rights to copy,
+modify and redistribute granted by the license, users are provided only
+with a limited warranty  and the software's author,  the holder of the
+economic rights,  and the successive licensors  have only  limited
+liability.
+
+In this respect, the user's attention is drawn to the risks associated
+with loading,  using,  modifying and/or developing or reproducing the
+software by the user in light of its specific status of free software,
+that may mean  that it is complicated to manipulate,  and  that  also
+therefore means  that it is reserved for developers  and  experienced
+professionals having in-depth computer knowledge. Users are therefore
+encouraged to load and test the software's suitability as regards their
+requirements in conditions enabling the security of their systems and/or
+data to be ensured and,  more generally, to use and operate it in the
+same conditions as regards security.
+
+The fact that you are presently reading this means that you have had
+knowledge of the CeCILL-B license and that you accept its terms.*/
+
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_triton-rust")).args(args).output().unwrap()
+}
+
+fn repository(name: &str, config: Option<&str>) -> PathBuf {
+    let path = env::temp_dir().join(format!("triton-rust-cli-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(path.join("model/1")).unwrap();
+    fs::write(path.join("model/1/model.onnx"), b"").unwrap();
+    if let Some(config) = config {
+        fs::write(path.join("model/config.pbtxt"), config).unwrap();
+    }
+
+    path
+}
+
+fn validate(path: &Path) -> Output {
+    run(&["validate", path.to_str().unwrap()])
+}
+
+#[test]
+fn valid_repository_exits_with_0() {
+    let path = repository("valid", Some(r#"platform: "onnxruntime_onnx" input { name: "x" data_type: TYPE_FP32 dims: [ 1 ] }"#));
+    let output = validate(&path);
+    fs::remove_dir_all(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).ends_with(": ok\n"));
+}
+
+#[test]
+fn issues_exit_with_1() {
+    let path = repository("issues", None);
+    let output = validate(&path);
+    fs::remove_dir_all(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("missing config.pbtxt"), "{}", stdout);
+    assert!(stdout.ends_with("1 issue(s) found\n"), "{}", stdout);
+}
+
+#[test]
+fn unreadable_repository_exits_with_2() {
+    let output = validate(&env::temp_dir().join("triton-rust-cli-missing"));
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("cannot read"));
+}
+
+#[test]
+fn usage_errors_exit_with_2() {
+    assert_eq!(run(&[]).status.code(), Some(2));
+    assert_eq!(run(&["validate"]).status.code(), Some(2));
+    assert_eq!(run(&["unknown", "command"]).status.code(), Some(2));
+    assert_eq!(run(&["--help"]).status.code(), Some(0));
+}